@@ -0,0 +1,119 @@
+use rusqlite::{OptionalExtension, ToSql};
+
+impl crate::NBMStore {
+    /// Add an alias for a site, e.g. "the lolo pass site" for "LOLM8".
+    ///
+    /// Aliases are saved in the local store and checked first by [Self::validate_request()], before
+    /// site ids and fuzzy matching, unless the request is an explicit "id:" query. They are case
+    /// insensitive, and adding an alias that already exists replaces it. The site id isn't
+    /// checked until the alias is used.
+    pub fn add_alias(&self, alias: &str, site_id: &str) -> Result<(), crate::Error> {
+        self.metadata.add_alias(alias, site_id)
+    }
+
+    /// Remove an alias, returns `false` if there was no such alias.
+    pub fn remove_alias(&self, alias: &str) -> Result<bool, crate::Error> {
+        self.metadata.remove_alias(alias)
+    }
+
+    /// All the aliases as (alias, site id) pairs, sorted by alias.
+    pub fn aliases(&self) -> Result<Vec<(String, String)>, crate::Error> {
+        self.metadata.aliases()
+    }
+}
+
+impl crate::metadata::Metadata {
+    pub(crate) fn add_alias(&self, alias: &str, site_id: &str) -> Result<(), crate::Error> {
+        const INSERT_ALIAS: &'static str = r#"
+            INSERT OR REPLACE INTO nbmarch_aliases (alias, site_id) VALUES (?, ?)
+        "#;
+
+        let alias = alias.trim();
+        let site_id = site_id.trim();
+        if alias.is_empty() || site_id.is_empty() {
+            return Err(crate::Error::general_error(format!(
+                "Invalid alias: '{}' for '{}'",
+                alias, site_id
+            )));
+        }
+
+        self.conn
+            .execute(INSERT_ALIAS, &[&alias as &dyn ToSql, &site_id])?;
+
+        Ok(())
+    }
+
+    pub(crate) fn remove_alias(&self, alias: &str) -> Result<bool, crate::Error> {
+        const DELETE_ALIAS: &'static str = r#"
+            DELETE FROM nbmarch_aliases WHERE alias = ?
+        "#;
+
+        Ok(self.conn.execute(DELETE_ALIAS, &[&alias.trim()])? > 0)
+    }
+
+    pub(crate) fn aliases(&self) -> Result<Vec<(String, String)>, crate::Error> {
+        const QUERY_ALIASES: &'static str = r#"
+            SELECT alias, site_id FROM nbmarch_aliases ORDER BY alias
+        "#;
+
+        let mut stmt = self.conn.prepare(QUERY_ALIASES)?;
+        let temp = stmt.query_map(rusqlite::NO_PARAMS, |row| Ok((row.get(0)?, row.get(1)?)))?;
+
+        Ok(temp.filter_map(|res| res.ok()).collect())
+    }
+}
+
+/// Look up the site id for an alias.
+pub(crate) fn resolve_alias(
+    conn: &rusqlite::Connection,
+    alias: &str,
+) -> Result<Option<String>, crate::Error> {
+    const QUERY_ALIAS: &'static str = r#"
+        SELECT site_id FROM nbmarch_aliases WHERE alias = ?
+    "#;
+
+    Ok(conn
+        .query_row(QUERY_ALIAS, &[&alias.trim()], |row| row.get(0))
+        .optional()?)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::site_validation::test::test_metadata;
+
+    #[test]
+    fn test_aliases() -> Result<(), Box<dyn std::error::Error>> {
+        let test = test_metadata()?;
+        let (metadata, init_time) = (&test.metadata, test.init_time);
+
+        metadata.add_alias("The Logan Pass site", "LGNM8")?;
+        metadata.add_alias("home", "KMSO")?;
+        assert!(metadata.add_alias(" ", "KMSO").is_err());
+
+        let locations = metadata.locations(init_time)?.unwrap();
+        assert_eq!(&locations.validate("the logan pass site")?.id, "LGNM8");
+        assert_eq!(&locations.validate("HOME")?.id, "KMSO");
+
+        // A 3 letter alias wins over expanding it to a "K" id, but not over an explicit id.
+        assert_eq!(&locations.validate("MSO")?.id, "KMSO");
+        metadata.add_alias("MSO", "LGNM8")?;
+        let locations = metadata.locations(init_time)?.unwrap();
+        assert_eq!(&locations.validate("mso")?.id, "LGNM8");
+        assert_eq!(&locations.validate("id:KMSO")?.id, "KMSO");
+        assert!(metadata.remove_alias("mso")?);
+
+        assert_eq!(
+            metadata.aliases()?,
+            vec![
+                ("home".to_owned(), "KMSO".to_owned()),
+                ("The Logan Pass site".to_owned(), "LGNM8".to_owned())
+            ]
+        );
+
+        assert!(metadata.remove_alias("Home")?);
+        assert!(!metadata.remove_alias("home")?);
+        assert_eq!(metadata.aliases()?.len(), 1);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,410 @@
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+/// The future returned by [AsyncFetcher::fetch()].
+pub type FetchFuture<'a> =
+    std::pin::Pin<Box<dyn std::future::Future<Output = Result<String, crate::Error>> + Send + 'a>>;
+
+/// The async version of [Fetcher](crate::Fetcher), the [AsyncNBMStore] uses it to retrieve any
+/// file that is not already in its local store.
+///
+/// The default is a `reqwest::Client`, which downloads from the NOAA servers. This trait is only
+/// available with the `async` feature.
+pub trait AsyncFetcher: Send + Sync {
+    /// Fetch the contents of the file at `url` as text, see
+    /// [Fetcher::fetch()](crate::Fetcher::fetch).
+    fn fetch<'a>(&'a self, url: &'a str) -> FetchFuture<'a>;
+}
+
+impl AsyncFetcher for reqwest::Client {
+    fn fetch<'a>(&'a self, url: &'a str) -> FetchFuture<'a> {
+        Box::pin(async move {
+            let response = self.get(url).send().await?;
+
+            let status = response.status();
+            if !status.is_success() {
+                return Err(crate::Error::Http {
+                    url: url.to_owned(),
+                    status: status.as_u16(),
+                });
+            }
+
+            Ok(response.text().await?)
+        })
+    }
+}
+
+/// An async interface to the same storage as [NBMStore](crate::NBMStore).
+///
+/// Files are downloaded with an [AsyncFetcher], by default reqwest's async client, using the same
+/// [RetryPolicy](crate::RetryPolicy) rules as the [NBMStore](crate::NBMStore). Parsing and all
+/// access to the local store run on tokio's blocking thread pool, so none of these methods block
+/// the async runtime. This type is only available with the `async` feature.
+#[derive(Clone)]
+pub struct AsyncNBMStore {
+    local_store: Arc<Mutex<filedb::FileDB>>,
+    metadata: Arc<Mutex<crate::metadata::Metadata>>,
+    fetcher: Arc<dyn AsyncFetcher>,
+    retry: crate::RetryPolicy,
+    access_mode: crate::AccessMode,
+    schedule: crate::CycleSchedule,
+    versions: crate::VersionTable,
+}
+
+impl AsyncNBMStore {
+    /// Connect to a store, see [NBMStore::connect()](crate::NBMStore::connect).
+    pub async fn connect<'a, OP: Into<Option<&'a std::path::Path>>>(
+        path: OP,
+    ) -> Result<Self, crate::Error> {
+        let path: Option<&std::path::Path> = path.into();
+
+        let path_buf: std::path::PathBuf = match path {
+            Some(p) => std::path::PathBuf::from(p),
+            None => crate::NBMStore::default_local_store_path()?,
+        };
+
+        let (local_store, metadata) = tokio::task::spawn_blocking(move || {
+            let local_store = filedb::FileDB::connect(&path_buf)?;
+            let metadata = crate::metadata::Metadata::connect(&path_buf)?;
+            metadata.backfill()?;
+            Ok::<_, crate::Error>((local_store, metadata))
+        })
+        .await??;
+
+        Ok(Self {
+            local_store: Arc::new(Mutex::new(local_store)),
+            metadata: Arc::new(Mutex::new(metadata)),
+            fetcher: Arc::new(reqwest::Client::new()),
+            retry: crate::RetryPolicy::default(),
+            access_mode: crate::AccessMode::default(),
+            schedule: crate::CycleSchedule::default(),
+            versions: crate::VersionTable::default(),
+        })
+    }
+
+    /// Use a pre-configured client for downloads, e.g. one with a proxy or custom timeouts.
+    pub fn with_client(self, client: reqwest::Client) -> Self {
+        self.with_fetcher(client)
+    }
+
+    /// Use a different [AsyncFetcher] for retrieving files that are not in the local store.
+    pub fn with_fetcher<F: AsyncFetcher + 'static>(mut self, fetcher: F) -> Self {
+        self.fetcher = Arc::new(fetcher);
+        self
+    }
+
+    /// Set the [AccessMode](crate::AccessMode) for this store.
+    pub fn with_access_mode(mut self, mode: crate::AccessMode) -> Self {
+        self.access_mode = mode;
+        self
+    }
+
+    /// Set the [CycleSchedule](crate::CycleSchedule) used to map request times to NBM
+    /// initialization times.
+    pub fn with_cycle_schedule(mut self, schedule: crate::CycleSchedule) -> Self {
+        self.schedule = schedule;
+        self
+    }
+
+    /// Use a different [VersionTable](crate::VersionTable), see
+    /// [NBMStore::with_version_table()](crate::NBMStore::with_version_table).
+    pub fn with_version_table(mut self, versions: crate::VersionTable) -> Self {
+        self.versions = versions;
+        self
+    }
+
+    /// Add a new NBM version that started with the initialization time `starts`, see
+    /// [VersionTable::register()](crate::VersionTable::register).
+    pub fn register_nbm_version(
+        &mut self,
+        starts: chrono::NaiveDateTime,
+        version: crate::NBMVersion,
+    ) {
+        self.versions.register(starts, version);
+    }
+
+    /// The version of the NBM that produced the files for an initialization time.
+    pub fn nbm_version(&self, init_time: chrono::NaiveDateTime) -> &crate::NBMVersion {
+        self.versions.version_at(init_time)
+    }
+
+    /// Set the [RetryPolicy](crate::RetryPolicy) used when downloading files.
+    pub fn with_retry_policy(mut self, policy: crate::RetryPolicy) -> Self {
+        self.retry = policy;
+        self
+    }
+
+    /// Validate a request, see [NBMStore::validate_request()](crate::NBMStore::validate_request).
+    pub async fn validate_request(
+        &self,
+        site: &str,
+        request_time: chrono::NaiveDateTime,
+    ) -> Result<crate::SiteValidation, crate::Error> {
+        let init_time = self.schedule.most_recent_initialization_time(request_time);
+        let nbm_version = self.nbm_version(init_time).clone();
+
+        if self.access_mode != crate::AccessMode::Refresh {
+            let metadata = self.metadata.clone();
+            let site = site.to_owned();
+            let validated = tokio::task::spawn_blocking(move || {
+                match lock(&metadata).locations(init_time)? {
+                    Some(locations) => locations.validate(&site).map(Some),
+                    None => Ok(None),
+                }
+            })
+            .await??;
+
+            if let Some(site_info) = validated {
+                return Ok(crate::SiteValidation::new(
+                    site_info,
+                    init_time,
+                    nbm_version,
+                ));
+            }
+        }
+
+        let locations_str = match self
+            .load_file("locations.csv", init_time, |text| {
+                crate::site_validation::parse_locations(text)?;
+                Ok(text.to_owned())
+            })
+            .await
+        {
+            Ok((text, _)) => text,
+            Err(err) if crate::download::is_not_available(&err) => {
+                return Err(crate::Error::InitializationTimeNotAvailable(init_time))
+            }
+            Err(err) => return Err(err),
+        };
+
+        let metadata = self.metadata.clone();
+        let site = site.to_owned();
+        tokio::task::spawn_blocking(move || {
+            lock(&metadata)
+                .add_locations(init_time, &locations_str)?
+                .validate(&site)
+                .map(|site_info| crate::SiteValidation::new(site_info, init_time, nbm_version))
+        })
+        .await?
+    }
+
+    /// Validate a request, but keep going back in time until an available initialization time
+    /// is found, see
+    /// [NBMStore::validate_most_recent_available()](crate::NBMStore::validate_most_recent_available).
+    pub async fn validate_most_recent_available(
+        &self,
+        site: &str,
+        request_time: chrono::NaiveDateTime,
+    ) -> Result<crate::SiteValidation, crate::Error> {
+        let mut attempts_left = 20_i32;
+        let mut attempt_request_time = request_time;
+
+        loop {
+            let validation = self.validate_request(site, attempt_request_time).await;
+
+            match &validation {
+                Err(crate::Error::InitializationTimeNotAvailable(init_time))
+                | Err(crate::Error::NotInLocalArchive { init_time, .. }) => {
+                    attempt_request_time = *init_time - chrono::Duration::hours(1);
+                }
+                _ => return validation,
+            }
+
+            attempts_left -= 1;
+            if attempts_left < 1 {
+                return validation;
+            }
+        }
+    }
+
+    /// Once a validation has been completed, it can be used to load a text file.
+    pub async fn retrieve(
+        &self,
+        validation: crate::SiteValidation,
+    ) -> Result<nbm_tools::NBMData, crate::Error> {
+        self.retrieve_with_provenance(validation)
+            .await
+            .map(|retrieved| retrieved.data)
+    }
+
+    /// Load a text file and report where it came from, see
+    /// [NBMStore::retrieve_with_provenance()](crate::NBMStore::retrieve_with_provenance).
+    pub async fn retrieve_with_provenance(
+        &self,
+        validation: crate::SiteValidation,
+    ) -> Result<crate::Retrieved, crate::Error> {
+        let file_name = validation.file_name();
+        let init_time = validation.initialization_time;
+
+        let (data, source) = self
+            .load_file(&file_name, init_time, |text| {
+                Ok(nbm_tools::NBMData::from_str(text)?)
+            })
+            .await?;
+
+        let metadata = self.metadata.clone();
+        let nbm_version = self.nbm_version(init_time).clone();
+        let provenance = tokio::task::spawn_blocking(move || {
+            crate::cache::provenance(&lock(&metadata), &nbm_version, &file_name, init_time)
+        })
+        .await??;
+
+        Ok(crate::Retrieved {
+            data,
+            source,
+            provenance,
+        })
+    }
+
+    /// The async version of `NBMStore::load_file()`.
+    async fn load_file<T, F>(
+        &self,
+        file_name: &str,
+        init_time: chrono::NaiveDateTime,
+        parse: F,
+    ) -> Result<(T, crate::Source), crate::Error>
+    where
+        T: Send + 'static,
+        F: Fn(&str) -> Result<T, crate::Error> + Send + 'static,
+    {
+        let local_store = self.local_store.clone();
+        let access_mode = self.access_mode;
+        let fname = file_name.to_owned();
+        let cached = tokio::task::spawn_blocking(move || {
+            crate::cache::cached_text(&lock(&local_store), access_mode, &fname, init_time)
+        })
+        .await??;
+
+        if let Some(text) = cached {
+            return Ok((
+                tokio::task::spawn_blocking(move || parse(&text)).await??,
+                crate::Source::LocalStore,
+            ));
+        }
+
+        let text = self.download_file(file_name, init_time).await?;
+
+        let local_store = self.local_store.clone();
+        let metadata = self.metadata.clone();
+        let nbm_version = self.nbm_version(init_time).clone();
+        let fname = file_name.to_owned();
+        let value = tokio::task::spawn_blocking(move || {
+            // Make sure it parses before it goes into the local store.
+            let value = parse(&text)?;
+
+            crate::cache::store_text(
+                &lock(&local_store),
+                &lock(&metadata),
+                &nbm_version,
+                &fname,
+                init_time,
+                &text,
+            )?;
+
+            Ok::<_, crate::Error>(value)
+        })
+        .await??;
+
+        Ok((value, crate::Source::Network))
+    }
+
+    /// The async version of `Downloader::download_file()`.
+    async fn download_file(
+        &self,
+        file_name: &str,
+        init_time: chrono::NaiveDateTime,
+    ) -> Result<String, crate::Error> {
+        let url =
+            crate::download::build_download_url(file_name, init_time, self.nbm_version(init_time));
+
+        let mut attempt = 1;
+        loop {
+            let err = match self
+                .fetcher
+                .fetch(&url)
+                .await
+                .and_then(|text| crate::download::check_content(&url, text))
+            {
+                Ok(text) => return Ok(text),
+                Err(err) => err,
+            };
+
+            tokio::time::sleep(self.retry.after_failure(attempt, err)?).await;
+            attempt += 1;
+        }
+    }
+}
+
+fn lock<T>(db: &Mutex<T>) -> std::sync::MutexGuard<T> {
+    // A panic while holding the lock can't leave the database in a bad state, so just carry on.
+    db.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Serves the fixtures, the files are small enough that reading them doesn't block for long.
+    struct Fixtures(crate::ReplayFetcher);
+
+    impl AsyncFetcher for Fixtures {
+        fn fetch<'a>(&'a self, url: &'a str) -> FetchFuture<'a> {
+            use crate::Fetcher;
+
+            let res = self.0.fetch(url);
+            Box::pin(async move { res })
+        }
+    }
+
+    fn fixtures() -> Fixtures {
+        Fixtures(crate::ReplayFetcher::new(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/fixtures"
+        )))
+    }
+
+    #[tokio::test]
+    async fn test_download_then_cached() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_db_file = tempfile::NamedTempFile::new()?;
+        let arch = AsyncNBMStore::connect(temp_db_file.path())
+            .await?
+            .with_fetcher(fixtures());
+
+        let request_time = chrono::NaiveDate::from_ymd(2021, 2, 28).and_hms(15, 15, 0);
+        let validation = arch.validate_request("missoula", request_time).await?;
+        assert_eq!(&validation.site.id, "KMSO");
+        assert_eq!(
+            validation.initialization_time,
+            chrono::NaiveDate::from_ymd(2021, 2, 28).and_hms(13, 0, 0)
+        );
+
+        let downloaded = arch.retrieve_with_provenance(validation.clone()).await?;
+        assert_eq!(downloaded.source, crate::Source::Network);
+        assert!(downloaded.provenance.downloaded.is_some());
+
+        let cached = arch.retrieve_with_provenance(validation).await?;
+        assert_eq!(cached.source, crate::Source::LocalStore);
+        assert_eq!(cached.provenance, downloaded.provenance);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_cache_only_miss() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_db_file = tempfile::NamedTempFile::new()?;
+        let arch = AsyncNBMStore::connect(temp_db_file.path())
+            .await?
+            .with_fetcher(fixtures())
+            .with_access_mode(crate::AccessMode::CacheOnly);
+
+        let init_time = chrono::NaiveDate::from_ymd(2021, 2, 28).and_hms(13, 0, 0);
+        match arch.validate_request("KMSO", init_time).await {
+            Err(crate::Error::NotInLocalArchive { file_name, .. }) => {
+                assert_eq!(file_name, "locations.csv")
+            }
+            res => panic!("Expected NotInLocalArchive, got {:?}", res),
+        }
+
+        Ok(())
+    }
+}
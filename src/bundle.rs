@@ -0,0 +1,376 @@
+use std::io::Read;
+use std::str::FromStr;
+
+/// Which files to include in an export, see [NBMStore::export()](crate::NBMStore::export).
+///
+/// The default includes everything.
+#[derive(Debug, Clone, Default)]
+pub struct ExportFilter {
+    /// Only export these site ids. The "locations.csv" file is always included for each exported
+    /// initialization time.
+    pub sites: Option<Vec<String>>,
+    /// Only export these initialization times.
+    pub init_times: Option<std::ops::RangeInclusive<chrono::NaiveDateTime>>,
+}
+
+impl ExportFilter {
+    fn includes(&self, file: &crate::StoredFile) -> bool {
+        let in_range = self
+            .init_times
+            .as_ref()
+            .map(|range| range.contains(&file.init_time))
+            .unwrap_or(true);
+
+        let site_id = file.file_name.trim_end_matches(".csv");
+        let in_sites = file.file_name == "locations.csv"
+            || self
+                .sites
+                .as_ref()
+                .map(|sites| sites.iter().any(|site| site.eq_ignore_ascii_case(site_id)))
+                .unwrap_or(true);
+
+        in_range && in_sites
+    }
+}
+
+/// The result of [NBMStore::import()](crate::NBMStore::import).
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ImportReport {
+    /// The number of files added to the local store.
+    pub imported: usize,
+    /// The number of files that were already in the local store with the same contents.
+    pub duplicates: usize,
+    /// Files that were already in the local store with different contents. The copy in the local
+    /// store is kept.
+    pub conflicts: Vec<(String, chrono::NaiveDateTime)>,
+    /// Files that didn't pass the checks for downloaded files, e.g. they aren't valid NBM 1D
+    /// files. These are not imported.
+    pub invalid: Vec<(String, chrono::NaiveDateTime)>,
+}
+
+/// The first entry in every bundle, it lists the files and their provenance.
+const MANIFEST: &str = "manifest.csv";
+
+const TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+impl crate::NBMStore {
+    /// Write some of the files in the local store to a bundle (a .tar.gz file) that can be
+    /// imported into another store with [Self::import()].
+    ///
+    /// The provenance of each file goes along with it. Returns the number of files exported.
+    pub fn export(
+        &self,
+        filter: &ExportFilter,
+        path: &std::path::Path,
+    ) -> Result<usize, crate::Error> {
+        let files: Vec<crate::StoredFile> = self
+            .stored_files()?
+            .into_iter()
+            .filter(|file| filter.includes(file))
+            .collect();
+
+        let mut manifest = csv::Writer::from_writer(vec![]);
+        manifest.write_record(&[
+            "file_name",
+            "init_time",
+            "url",
+            "downloaded",
+            "version_name",
+            "version_path",
+        ])?;
+
+        for file in &files {
+            let provenance = self
+                .metadata
+                .provenance(&file.file_name, file.init_time)?
+                .unwrap_or_else(|| {
+                    crate::Provenance::unknown(
+                        &file.file_name,
+                        file.init_time,
+                        self.nbm_version(file.init_time),
+                    )
+                });
+
+            let downloaded = provenance
+                .downloaded
+                .map(|dt| dt.format(TIME_FORMAT).to_string())
+                .unwrap_or_default();
+
+            manifest.write_record(&[
+                file.file_name.as_str(),
+                &file.init_time.format(TIME_FORMAT).to_string(),
+                &provenance.url,
+                &downloaded,
+                &provenance.nbm_version.name,
+                &provenance.nbm_version.path,
+            ])?;
+        }
+
+        let manifest = manifest
+            .into_inner()
+            .map_err(|err| crate::Error::general_error(err.to_string()))?;
+
+        let bundle = std::fs::File::create(path)?;
+        let encoder = flate2::write::GzEncoder::new(bundle, flate2::Compression::default());
+        let mut builder = tar::Builder::new(encoder);
+
+        append(&mut builder, MANIFEST, &manifest)?;
+
+        let mut exported = 0;
+        for file in &files {
+            if let Some(bytes) = self
+                .local_store
+                .retrieve_file(&file.file_name, file.init_time)?
+            {
+                append(
+                    &mut builder,
+                    &bundle_path(&file.file_name, file.init_time),
+                    &bytes,
+                )?;
+                exported += 1;
+            }
+        }
+
+        builder.into_inner()?.finish()?;
+
+        Ok(exported)
+    }
+
+    /// Merge a bundle written by [Self::export()] into the local store.
+    ///
+    /// Files already in the local store are never replaced, and files are checked and parsed just
+    /// like downloaded files before they are added, see [ImportReport].
+    pub fn import(&self, path: &std::path::Path) -> Result<ImportReport, crate::Error> {
+        let bundle = std::fs::File::open(path)?;
+        let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(bundle));
+
+        let mut manifest = None;
+        let mut report = ImportReport::default();
+
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            let entry_path = entry.path()?.to_string_lossy().into_owned();
+
+            let mut bytes = vec![];
+            entry.read_to_end(&mut bytes)?;
+
+            if entry_path == MANIFEST {
+                manifest = Some(parse_manifest(&bytes)?);
+                continue;
+            }
+
+            let (file_name, init_time, provenance) = match manifest
+                .as_ref()
+                .ok_or_else(|| {
+                    crate::Error::general_error(format!(
+                        "{} doesn't start with a manifest",
+                        path.display()
+                    ))
+                })?
+                .get(&entry_path)
+            {
+                Some(record) => record,
+                None => continue,
+            };
+
+            match self.local_store.retrieve_file(file_name, *init_time)? {
+                Some(existing) if existing == bytes => report.duplicates += 1,
+                Some(_) => report.conflicts.push((file_name.clone(), *init_time)),
+                None => match validate_file(file_name, provenance, bytes) {
+                    Ok(text) => {
+                        self.local_store
+                            .add_file(file_name, *init_time, text.as_bytes())?;
+                        self.metadata
+                            .add_file(file_name, *init_time, provenance, text.len())?;
+                        report.imported += 1;
+                    }
+                    Err(_) => report.invalid.push((file_name.clone(), *init_time)),
+                },
+            }
+        }
+
+        Ok(report)
+    }
+}
+
+/// Run a file from a bundle through the same checks as a downloaded file.
+fn validate_file(
+    file_name: &str,
+    provenance: &crate::Provenance,
+    bytes: Vec<u8>,
+) -> Result<String, crate::Error> {
+    let text = crate::download::check_content(&provenance.url, String::from_utf8(bytes)?)?;
+
+    if file_name == "locations.csv" {
+        crate::site_validation::parse_locations(&text)?;
+    } else {
+        nbm_tools::NBMData::from_str(&text)?;
+    }
+
+    Ok(text)
+}
+
+type ManifestRecord = (String, chrono::NaiveDateTime, crate::Provenance);
+
+/// Read the manifest into a map from the path in the bundle to the file it holds.
+fn parse_manifest(
+    bytes: &[u8],
+) -> Result<std::collections::HashMap<String, ManifestRecord>, crate::Error> {
+    let mut rdr = csv::Reader::from_reader(bytes);
+
+    let mut manifest = std::collections::HashMap::new();
+    for rec in rdr.records() {
+        let rec = rec?;
+
+        let field = |idx: usize| rec.get(idx).unwrap_or("");
+        let parse_time = |val: &str| chrono::NaiveDateTime::parse_from_str(val, TIME_FORMAT);
+
+        let file_name = field(0).to_owned();
+        let init_time = parse_time(field(1))
+            .map_err(|err| crate::Error::general_error(format!("Invalid manifest: {}", err)))?;
+
+        let provenance = crate::Provenance {
+            url: field(2).to_owned(),
+            downloaded: parse_time(field(3)).ok(),
+            nbm_version: crate::NBMVersion::new(field(4), field(5)),
+        };
+
+        manifest.insert(
+            bundle_path(&file_name, init_time),
+            (file_name, init_time, provenance),
+        );
+    }
+
+    Ok(manifest)
+}
+
+fn bundle_path(file_name: &str, init_time: chrono::NaiveDateTime) -> String {
+    format!("files/{}/{}", init_time.format("%Y%m%d%H"), file_name)
+}
+
+fn append<W: std::io::Write>(
+    builder: &mut tar::Builder<W>,
+    path: &str,
+    bytes: &[u8],
+) -> Result<(), crate::Error> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(bytes.len() as u64);
+    header.set_mode(0o644);
+
+    Ok(builder.append_data(&mut header, path, bytes)?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const LOCATIONS: &str = include_str!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/fixtures/2021/02/28/NBM4.0/13/locations.csv"
+    ));
+    const KMSO: &str = include_str!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/fixtures/2021/02/28/NBM4.0/13/KMSO.csv"
+    ));
+    const KGPI: &str = include_str!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/fixtures/2021/02/28/NBM4.0/13/KGPI.csv"
+    ));
+
+    #[test]
+    fn test_export_import() -> Result<(), Box<dyn std::error::Error>> {
+        let from_db_file = tempfile::NamedTempFile::new()?;
+        let from = crate::NBMStore::connect(from_db_file.path())?;
+
+        let init_time = chrono::NaiveDate::from_ymd(2021, 2, 28).and_hms(13, 0, 0);
+        from.store_file("locations.csv", init_time, LOCATIONS)?;
+        from.store_file("KMSO.csv", init_time, KMSO)?;
+        from.store_file("KGPI.csv", init_time, KGPI)?;
+
+        let bundle_file = tempfile::NamedTempFile::new()?;
+        let filter = ExportFilter {
+            sites: Some(vec!["kmso".to_owned()]),
+            ..ExportFilter::default()
+        };
+        assert_eq!(from.export(&filter, bundle_file.path())?, 2);
+
+        let to_db_file = tempfile::NamedTempFile::new()?;
+        let to = crate::NBMStore::connect(to_db_file.path())?;
+        to.store_file(
+            "locations.csv",
+            init_time,
+            "id,name,state,lat,lon\nKMSO,X,MT,1,1\n",
+        )?;
+
+        let report = to.import(bundle_file.path())?;
+        assert_eq!(report.imported, 1);
+        assert_eq!(report.duplicates, 0);
+        assert_eq!(
+            report.conflicts,
+            vec![("locations.csv".to_owned(), init_time)]
+        );
+
+        let imported = to.local_store.retrieve_file("KMSO.csv", init_time)?;
+        assert_eq!(imported, Some(KMSO.as_bytes().to_vec()));
+        assert_eq!(
+            to.metadata.provenance("KMSO.csv", init_time)?,
+            from.metadata.provenance("KMSO.csv", init_time)?
+        );
+        assert!(to
+            .local_store
+            .retrieve_file("KGPI.csv", init_time)?
+            .is_none());
+
+        let report = to.import(bundle_file.path())?;
+        assert_eq!(report.imported, 0);
+        assert_eq!(report.duplicates, 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_import_rejects_invalid_files() -> Result<(), Box<dyn std::error::Error>> {
+        let from_db_file = tempfile::NamedTempFile::new()?;
+        let from = crate::NBMStore::connect(from_db_file.path())?;
+
+        let init_time = chrono::NaiveDate::from_ymd(2021, 2, 28).and_hms(13, 0, 0);
+        from.store_file("locations.csv", init_time, "id,name,state,lat,lon\n")?;
+        from.store_file("KMSO.csv", init_time, "<html>Not Found</html>")?;
+        from.store_file("KGPI.csv", init_time, KGPI)?;
+
+        let bundle_file = tempfile::NamedTempFile::new()?;
+        assert_eq!(
+            from.export(&ExportFilter::default(), bundle_file.path())?,
+            3
+        );
+
+        let to_db_file = tempfile::NamedTempFile::new()?;
+        let to = crate::NBMStore::connect(to_db_file.path())?;
+
+        let mut report = to.import(bundle_file.path())?;
+        report.invalid.sort();
+        assert_eq!(report.imported, 1);
+        assert_eq!(
+            report.invalid,
+            vec![
+                ("KMSO.csv".to_owned(), init_time),
+                ("locations.csv".to_owned(), init_time)
+            ]
+        );
+
+        assert!(to
+            .local_store
+            .retrieve_file("KMSO.csv", init_time)?
+            .is_none());
+        assert!(to
+            .local_store
+            .retrieve_file("locations.csv", init_time)?
+            .is_none());
+        assert_eq!(
+            to.local_store.retrieve_file("KGPI.csv", init_time)?,
+            Some(KGPI.as_bytes().to_vec())
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,54 @@
+/// Look for a file in the local store, following the [AccessMode](crate::AccessMode).
+///
+/// Returns `None` when the file should be downloaded, which is always the case in
+/// [AccessMode::Refresh](crate::AccessMode::Refresh).
+pub(crate) fn cached_text(
+    local_store: &filedb::FileDB,
+    access_mode: crate::AccessMode,
+    file_name: &str,
+    init_time: chrono::NaiveDateTime,
+) -> Result<Option<String>, crate::Error> {
+    if access_mode != crate::AccessMode::Refresh {
+        if let Some(bytes) = local_store.retrieve_file(file_name, init_time)? {
+            return Ok(Some(String::from_utf8(bytes)?));
+        }
+    }
+
+    if access_mode == crate::AccessMode::CacheOnly {
+        return Err(crate::Error::NotInLocalArchive {
+            file_name: file_name.to_owned(),
+            init_time,
+        });
+    }
+
+    Ok(None)
+}
+
+/// Add a downloaded file to the local store along with its provenance.
+pub(crate) fn store_text(
+    local_store: &filedb::FileDB,
+    metadata: &crate::metadata::Metadata,
+    nbm_version: &crate::NBMVersion,
+    file_name: &str,
+    init_time: chrono::NaiveDateTime,
+    text: &str,
+) -> Result<(), crate::Error> {
+    let provenance = crate::Provenance::downloaded_now(file_name, init_time, nbm_version);
+
+    local_store.add_file(file_name, init_time, text.as_bytes())?;
+    metadata.add_file(file_name, init_time, &provenance, text.len())
+}
+
+/// Get the [Provenance](crate::Provenance) of a file in the local store, or the best guess for
+/// files that don't have one saved.
+pub(crate) fn provenance(
+    metadata: &crate::metadata::Metadata,
+    nbm_version: &crate::NBMVersion,
+    file_name: &str,
+    init_time: chrono::NaiveDateTime,
+) -> Result<crate::Provenance, crate::Error> {
+    Ok(match metadata.provenance(file_name, init_time)? {
+        Some(provenance) => provenance,
+        None => crate::Provenance::unknown(file_name, init_time, nbm_version),
+    })
+}
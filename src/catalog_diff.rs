@@ -0,0 +1,173 @@
+/// The changes to the site catalog between two initialization times.
+///
+/// Sites are matched by id first. A site that disappeared and a new site that showed up in the
+/// same place are treated as the same site with a new id, so they're listed in `renamed` instead
+/// of `removed` and `added`.
+#[derive(Debug, Clone, Default)]
+pub struct CatalogDiff {
+    /// Sites only in the newer catalog.
+    pub added: Vec<crate::SiteInfo>,
+    /// Sites only in the older catalog.
+    pub removed: Vec<crate::SiteInfo>,
+    /// Sites with a new id, name, or state/province, as (old, new) pairs.
+    pub renamed: Vec<(crate::SiteInfo, crate::SiteInfo)>,
+    /// Sites that kept their id but moved, as (old, new) pairs.
+    pub relocated: Vec<(crate::SiteInfo, crate::SiteInfo)>,
+}
+
+impl CatalogDiff {
+    /// Check if the catalogs were the same.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty()
+            && self.removed.is_empty()
+            && self.renamed.is_empty()
+            && self.relocated.is_empty()
+    }
+}
+
+/// Sites that moved less than this (km) are considered to be in the same place.
+const SAME_PLACE_KM: f64 = 0.01;
+
+/// A removed site and an added site this close (km) are considered the same site with a new id.
+const NEW_ID_KM: f64 = 1.0;
+
+impl crate::NBMStore {
+    /// Compare the site catalogs ("locations.csv" files) for two initialization times.
+    ///
+    /// Each time is mapped to the most recent initialization time at or before it.
+    pub fn diff_sites(
+        &self,
+        old_init_time: chrono::NaiveDateTime,
+        new_init_time: chrono::NaiveDateTime,
+    ) -> Result<CatalogDiff, crate::Error> {
+        let old_init_time = self.schedule.most_recent_initialization_time(old_init_time);
+        let new_init_time = self.schedule.most_recent_initialization_time(new_init_time);
+
+        let old = self.locations(old_init_time)?.sites()?;
+        let new = self.locations(new_init_time)?.sites()?;
+
+        Ok(diff(old, new))
+    }
+}
+
+pub(crate) fn diff(old: Vec<crate::SiteInfo>, new: Vec<crate::SiteInfo>) -> CatalogDiff {
+    let mut new: std::collections::HashMap<String, crate::SiteInfo> = new
+        .into_iter()
+        .map(|site| (site.id.clone(), site))
+        .collect();
+
+    let mut diff = CatalogDiff::default();
+
+    for old_site in old {
+        let new_site = match new.remove(&old_site.id) {
+            Some(new_site) => new_site,
+            None => {
+                diff.removed.push(old_site);
+                continue;
+            }
+        };
+
+        let moved = distance(&old_site, &new_site) > SAME_PLACE_KM;
+        let renamed = old_site.name != new_site.name || old_site.state_prov != new_site.state_prov;
+
+        match (moved, renamed) {
+            (true, true) => {
+                diff.relocated.push((old_site.clone(), new_site.clone()));
+                diff.renamed.push((old_site, new_site));
+            }
+            (true, false) => diff.relocated.push((old_site, new_site)),
+            (false, true) => diff.renamed.push((old_site, new_site)),
+            (false, false) => {}
+        }
+    }
+
+    diff.added = new.into_values().collect();
+    diff.added.sort_by(|a, b| a.id.cmp(&b.id));
+
+    // Pair up sites that only changed their id.
+    let removed = std::mem::take(&mut diff.removed);
+    for old_site in removed {
+        let closest = diff
+            .added
+            .iter()
+            .enumerate()
+            .map(|(idx, new_site)| (idx, distance(&old_site, new_site)))
+            .filter(|(_, dist)| *dist <= NEW_ID_KM)
+            .min_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+
+        match closest {
+            Some((idx, _)) => {
+                let new_site = diff.added.remove(idx);
+                diff.renamed.push((old_site, new_site));
+            }
+            None => diff.removed.push(old_site),
+        }
+    }
+
+    diff
+}
+
+fn distance(a: &crate::SiteInfo, b: &crate::SiteInfo) -> f64 {
+    a.distance_to(f64::from(b.latitude), f64::from(b.longitude))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn site(id: &str, name: &str, latitude: f32, longitude: f32) -> crate::SiteInfo {
+        crate::SiteInfo {
+            id: id.to_owned(),
+            name: name.to_owned(),
+            state_prov: "MT".to_owned(),
+            latitude,
+            longitude,
+        }
+    }
+
+    #[test]
+    fn test_diff() {
+        let old = vec![
+            site("KMSO", "MISSOULA", 46.92, -114.09),
+            site("KGPI", "KALISPELL", 48.31, -114.26),
+            site("KBZN", "BOZEMAN", 45.78, -111.15),
+            site("LOLM8", "LOLO", 46.63, -114.58),
+            site("KHLN", "HELENA", 46.61, -111.98),
+        ];
+
+        let new = vec![
+            site("KMSO", "MISSOULA", 46.92, -114.09),
+            site("KGPI", "GLACIER PARK INTL", 48.31, -114.26),
+            site("KBZN", "BOZEMAN", 45.79, -111.16),
+            site("LOLO8", "LOLO PASS", 46.63, -114.58),
+            site("KBIL", "BILLINGS", 45.81, -108.54),
+        ];
+
+        let diff = diff(old, new);
+
+        let ids = |sites: &[crate::SiteInfo]| -> Vec<String> {
+            sites.iter().map(|site| site.id.clone()).collect()
+        };
+        let pairs = |pairs: &[(crate::SiteInfo, crate::SiteInfo)]| -> Vec<(String, String)> {
+            pairs
+                .iter()
+                .map(|(old, new)| (old.id.clone(), new.id.clone()))
+                .collect()
+        };
+
+        assert_eq!(ids(&diff.added), vec!["KBIL"]);
+        assert_eq!(ids(&diff.removed), vec!["KHLN"]);
+        assert_eq!(
+            pairs(&diff.renamed),
+            vec![
+                ("KGPI".to_owned(), "KGPI".to_owned()),
+                ("LOLM8".to_owned(), "LOLO8".to_owned())
+            ]
+        );
+        assert_eq!(
+            pairs(&diff.relocated),
+            vec![("KBZN".to_owned(), "KBZN".to_owned())]
+        );
+        assert!(!diff.is_empty());
+    }
+}
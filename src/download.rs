@@ -1,19 +1,268 @@
 use chrono::{Datelike, Timelike};
 
-pub fn download_file(
-    fname: &str,
-    init_time: chrono::NaiveDateTime,
-) -> Result<String, crate::Error> {
-    let url = build_download_url(fname, init_time);
+/// A source of NBM 1D text files.
+///
+/// The [NBMStore](crate::NBMStore) uses a fetcher to retrieve any file that is not already in its
+/// local store. The default is [ReqwestFetcher], which downloads from the NOAA servers, but any
+/// type implementing this trait can be plugged in with
+/// [NBMStore::with_fetcher()](crate::NBMStore::with_fetcher), e.g. a local mirror of the archive
+/// or a client for an authenticated proxy.
+///
+/// Closures with the signature `Fn(&str) -> Result<String, nbmarch::Error>` implement this trait
+/// too, which is handy for fakes in tests.
+pub trait Fetcher: Send + Sync {
+    /// Fetch the contents of the file at `url` as text.
+    ///
+    /// The url is always a full url rooted at the NOAA archive, e.g.
+    /// `https://hwp-viz.gsd.esrl.noaa.gov/wave1d/data/archive/2021/02/28/NBM4.0/13/KMSO.csv`.
+    ///
+    /// Implementations should return [Error::Http](crate::Error::Http) when the server responds
+    /// with an error status rather than returning the body of the error page.
+    fn fetch(&self, url: &str) -> Result<String, crate::Error>;
+}
+
+impl<F> Fetcher for F
+where
+    F: Fn(&str) -> Result<String, crate::Error> + Send + Sync,
+{
+    fn fetch(&self, url: &str) -> Result<String, crate::Error> {
+        self(url)
+    }
+}
+
+/// The default [Fetcher], it downloads files from the NOAA servers using reqwest.
+#[derive(Debug, Default)]
+pub struct ReqwestFetcher {
+    client: reqwest::blocking::Client,
+}
+
+impl ReqwestFetcher {
+    /// Create a new fetcher with a default client.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a new fetcher using a pre-configured client, e.g. one with a proxy or custom
+    /// timeouts.
+    pub fn with_client(client: reqwest::blocking::Client) -> Self {
+        Self { client }
+    }
+}
+
+impl Fetcher for ReqwestFetcher {
+    fn fetch(&self, url: &str) -> Result<String, crate::Error> {
+        let response = self.client.get(url).send()?;
+
+        let status = response.status();
+        if !status.is_success() {
+            return Err(crate::Error::Http {
+                url: url.to_owned(),
+                status: status.as_u16(),
+            });
+        }
+
+        Ok(response.text()?)
+    }
+}
+
+/// Controls how failed downloads are retried.
+///
+/// Delays grow exponentially, `base_delay * 2^(attempt - 1)`, capped at `max_delay`, and each one
+/// is randomly shortened by up to `jitter` (a fraction from 0.0 to 1.0) so that many clients don't
+/// retry in lock step.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// The maximum number of attempts, including the first one. A value of 1 disables retries.
+    pub max_attempts: u32,
+    /// The delay before the first retry.
+    pub base_delay: std::time::Duration,
+    /// The longest delay between any two attempts.
+    pub max_delay: std::time::Duration,
+    /// The fraction of each delay that may be randomly removed.
+    pub jitter: f64,
+    /// Decides which errors are worth another attempt.
+    pub is_retryable: fn(&crate::Error) -> bool,
+}
+
+impl RetryPolicy {
+    /// A policy that never retries.
+    pub fn no_retries() -> Self {
+        Self {
+            max_attempts: 1,
+            ..Self::default()
+        }
+    }
+
+    /// Decide what to do after a failed attempt, `attempt` starts at 1.
+    ///
+    /// Returns how long to wait before trying again, or the error to give up with.
+    pub(crate) fn after_failure(
+        &self,
+        attempt: u32,
+        err: crate::Error,
+    ) -> Result<std::time::Duration, crate::Error> {
+        if (self.is_retryable)(&err) && attempt < self.max_attempts {
+            return Ok(self.delay_after(attempt));
+        }
+
+        // Any failure after a retry says how many attempts were made, whatever ended them.
+        Err(if attempt > 1 {
+            crate::Error::RetriesExhausted {
+                attempts: attempt,
+                last: Box::new(err),
+            }
+        } else {
+            err
+        })
+    }
+
+    /// The delay to wait after a failed attempt, `attempt` starts at 1.
+    fn delay_after(&self, attempt: u32) -> std::time::Duration {
+        let factor = 2_u32.saturating_pow(attempt.saturating_sub(1));
+        let delay = self
+            .base_delay
+            .checked_mul(factor)
+            .unwrap_or(self.max_delay)
+            .min(self.max_delay);
+
+        let jitter = self.jitter.max(0.0).min(1.0) * random_fraction();
+        delay.mul_f64(1.0 - jitter)
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 4,
+            base_delay: std::time::Duration::from_millis(500),
+            max_delay: std::time::Duration::from_secs(30),
+            jitter: 0.5,
+            is_retryable: is_transient,
+        }
+    }
+}
+
+/// The default test for [RetryPolicy::is_retryable], timeouts, failed connections, server errors
+/// (5xx) and rate limiting (429) are considered transient.
+pub fn is_transient(err: &crate::Error) -> bool {
+    let is_transient_status = |status: u16| status >= 500 || status == 429;
+
+    match err {
+        crate::Error::Http { status, .. } => is_transient_status(*status),
+        crate::Error::Internal(err) => match err.downcast_ref::<reqwest::Error>() {
+            Some(err) => {
+                err.is_timeout()
+                    || err.is_connect()
+                    || err
+                        .status()
+                        .map(|status| is_transient_status(status.as_u16()))
+                        .unwrap_or(false)
+            }
+            None => false,
+        },
+        _ => false,
+    }
+}
+
+/// A number in [0, 1), good enough for jitter without pulling in a random number crate.
+fn random_fraction() -> f64 {
+    use std::hash::{BuildHasher, Hasher};
+
+    let mut hasher = std::collections::hash_map::RandomState::new().build_hasher();
+    hasher.write_u128(
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_nanos())
+            .unwrap_or(0),
+    );
+
+    (hasher.finish() >> 11) as f64 / (1_u64 << 53) as f64
+}
+
+/// Everything needed to get a file from the remote archive.
+#[derive(Clone)]
+pub(crate) struct Downloader {
+    pub(crate) fetcher: std::sync::Arc<dyn Fetcher>,
+    pub(crate) retry: RetryPolicy,
+    pub(crate) versions: crate::VersionTable,
+}
+
+impl Default for Downloader {
+    fn default() -> Self {
+        Self {
+            fetcher: std::sync::Arc::new(ReqwestFetcher::new()),
+            retry: RetryPolicy::default(),
+            versions: crate::VersionTable::default(),
+        }
+    }
+}
+
+impl Downloader {
+    pub(crate) fn download_file(
+        &self,
+        fname: &str,
+        init_time: chrono::NaiveDateTime,
+    ) -> Result<String, crate::Error> {
+        let url = build_download_url(fname, init_time, self.versions.version_at(init_time));
 
-    Ok(reqwest::blocking::get(&url)?.text()?)
+        let mut attempt = 1;
+        loop {
+            let err = match self
+                .fetcher
+                .fetch(&url)
+                .and_then(|text| check_content(&url, text))
+            {
+                Ok(text) => return Ok(text),
+                Err(err) => err,
+            };
+
+            std::thread::sleep(self.retry.after_failure(attempt, err)?);
+            attempt += 1;
+        }
+    }
+}
+
+/// Make sure the downloaded text is something that belongs in the local store.
+pub(crate) fn check_content(url: &str, text: String) -> Result<String, crate::Error> {
+    if looks_like_csv(&text) {
+        Ok(text)
+    } else {
+        Err(crate::Error::InvalidContent {
+            url: url.to_owned(),
+        })
+    }
 }
 
-fn build_download_url(fname: &str, init_time: chrono::NaiveDateTime) -> String {
-    // FIXME: when chrono makes these functions const, make this a const
-    let nbm_4_starts: chrono::NaiveDateTime =
-        chrono::NaiveDate::from_ymd(2020, 9, 23).and_hms(0, 0, 0);
+/// Check if an error means the file just isn't on the server, as opposed to a network or server
+/// failure.
+pub(crate) fn is_not_available(err: &crate::Error) -> bool {
+    match err {
+        crate::Error::Http { status, .. } => (400..500).contains(status),
+        crate::Error::InvalidContent { .. } => true,
+        crate::Error::RetriesExhausted { last, .. } => is_not_available(last),
+        _ => false,
+    }
+}
+
+/// A quick sanity check so error pages and other junk never make it into the local store.
+fn looks_like_csv(text: &str) -> bool {
+    let text = text.trim_start();
 
+    if text.starts_with('<') {
+        return false;
+    }
+
+    match text.lines().next() {
+        Some(header) => header.contains(','),
+        None => false,
+    }
+}
+
+pub(crate) fn build_download_url(
+    fname: &str,
+    init_time: chrono::NaiveDateTime,
+    version: &crate::NBMVersion,
+) -> String {
     let year = init_time.year();
     let month = init_time.month();
     let day = init_time.day();
@@ -21,21 +270,175 @@ fn build_download_url(fname: &str, init_time: chrono::NaiveDateTime) -> String {
 
     let url_fname = format_file_name_for_download(fname);
 
-    if init_time > nbm_4_starts {
-        format!(
-            "{}{:04}/{:02}/{:02}/NBM4.0/{:02}/{}",
-            BASE_URL, year, month, day, hour, url_fname
-        )
-    } else {
-        format!(
-            "{}{:4}/{:02}/{:02}/NBM/{:02}/{}",
-            BASE_URL, year, month, day, hour, url_fname
-        )
-    }
+    format!(
+        "{}{:04}/{:02}/{:02}/{}/{:02}/{}",
+        BASE_URL, year, month, day, version.path, hour, url_fname
+    )
 }
 
 fn format_file_name_for_download(fname: &str) -> String {
     fname.replace(" ", "%20")
 }
 
-const BASE_URL: &'static str = "https://hwp-viz.gsd.esrl.noaa.gov/wave1d/data/archive/";
+/// The root of the NOAA archive of NBM 1D viewer files.
+pub const BASE_URL: &'static str = "https://hwp-viz.gsd.esrl.noaa.gov/wave1d/data/archive/";
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::Arc;
+
+    fn quick_policy() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 3,
+            base_delay: std::time::Duration::from_millis(1),
+            max_delay: std::time::Duration::from_millis(2),
+            ..RetryPolicy::default()
+        }
+    }
+
+    #[test]
+    fn test_retries_then_gives_up() {
+        let calls = Arc::new(AtomicU32::new(0));
+        let counter = calls.clone();
+
+        let downloader = Downloader {
+            fetcher: Arc::new(move |url: &str| {
+                counter.fetch_add(1, Ordering::SeqCst);
+                Err(crate::Error::Http {
+                    url: url.to_owned(),
+                    status: 503,
+                })
+            }),
+            retry: quick_policy(),
+            versions: crate::VersionTable::default(),
+        };
+
+        let init_time = chrono::NaiveDate::from_ymd(2021, 2, 28).and_hms(13, 0, 0);
+        match downloader.download_file("KMSO.csv", init_time) {
+            Err(crate::Error::RetriesExhausted { attempts: 3, .. }) => {}
+            res => panic!("Expected the retries to be exhausted, got {:?}", res),
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn test_does_not_retry_missing_files() {
+        let calls = Arc::new(AtomicU32::new(0));
+        let counter = calls.clone();
+
+        let downloader = Downloader {
+            fetcher: Arc::new(move |url: &str| {
+                counter.fetch_add(1, Ordering::SeqCst);
+                Err(crate::Error::Http {
+                    url: url.to_owned(),
+                    status: 404,
+                })
+            }),
+            retry: quick_policy(),
+            versions: crate::VersionTable::default(),
+        };
+
+        let init_time = chrono::NaiveDate::from_ymd(2021, 2, 28).and_hms(13, 0, 0);
+        match downloader.download_file("KMSO.csv", init_time) {
+            Err(crate::Error::Http { status: 404, .. }) => {}
+            res => panic!("Expected a 404 error, got {:?}", res),
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_counts_attempts_for_any_final_error() {
+        let calls = Arc::new(AtomicU32::new(0));
+        let counter = calls.clone();
+
+        let downloader = Downloader {
+            fetcher: Arc::new(move |url: &str| {
+                let status = if counter.fetch_add(1, Ordering::SeqCst) == 0 {
+                    503
+                } else {
+                    404
+                };
+                Err(crate::Error::Http {
+                    url: url.to_owned(),
+                    status,
+                })
+            }),
+            retry: quick_policy(),
+            versions: crate::VersionTable::default(),
+        };
+
+        let init_time = chrono::NaiveDate::from_ymd(2021, 2, 28).and_hms(13, 0, 0);
+        match downloader.download_file("KMSO.csv", init_time) {
+            Err(err @ crate::Error::RetriesExhausted { attempts: 2, .. }) => {
+                assert!(is_not_available(&err));
+                match err {
+                    crate::Error::RetriesExhausted { last, .. } => match *last {
+                        crate::Error::Http { status: 404, .. } => {}
+                        last => panic!("Expected a 404 error, got {:?}", last),
+                    },
+                    _ => unreachable!(),
+                }
+            }
+            res => panic!("Expected the attempts to be counted, got {:?}", res),
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_is_transient() {
+        let client = reqwest::blocking::Client::new();
+
+        // Nothing listens on port 1, so the connection is refused.
+        let connect_err: crate::Error =
+            client.get("http://127.0.0.1:1/").send().unwrap_err().into();
+        assert!(is_transient(&connect_err));
+
+        let builder_err: crate::Error = client.get("not a url").send().unwrap_err().into();
+        assert!(!is_transient(&builder_err));
+
+        let http_err = |status| crate::Error::Http {
+            url: BASE_URL.to_owned(),
+            status,
+        };
+        assert!(is_transient(&http_err(503)));
+        assert!(is_transient(&http_err(429)));
+        assert!(!is_transient(&http_err(404)));
+        assert!(!is_transient(&crate::Error::general_error(
+            "disk full".to_owned()
+        )));
+    }
+
+    #[test]
+    fn test_rejects_html() {
+        let downloader = Downloader {
+            fetcher: Arc::new(|_url: &str| Ok("<html><body>Not Found</body></html>".to_owned())),
+            retry: quick_policy(),
+            versions: crate::VersionTable::default(),
+        };
+
+        let init_time = chrono::NaiveDate::from_ymd(2021, 2, 28).and_hms(13, 0, 0);
+        match downloader.download_file("KMSO.csv", init_time) {
+            Err(crate::Error::InvalidContent { .. }) => {}
+            res => panic!("Expected invalid content, got {:?}", res),
+        }
+    }
+
+    #[test]
+    fn test_build_download_url() {
+        let versions = crate::VersionTable::default();
+
+        let init_time = chrono::NaiveDate::from_ymd(2020, 9, 22).and_hms(19, 0, 0);
+        assert_eq!(
+            build_download_url("KMSO.csv", init_time, versions.version_at(init_time)),
+            format!("{}2020/09/22/NBM/19/KMSO.csv", BASE_URL)
+        );
+
+        let init_time = chrono::NaiveDate::from_ymd(2021, 2, 28).and_hms(13, 0, 0);
+        assert_eq!(
+            build_download_url("locations.csv", init_time, versions.version_at(init_time)),
+            format!("{}2021/02/28/NBM4.0/13/locations.csv", BASE_URL)
+        );
+    }
+}
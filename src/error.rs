@@ -1,6 +1,6 @@
 use chrono;
 
-use crate::SiteInfo;
+use crate::SiteMatch;
 
 /// A general error type.
 #[derive(Debug)]
@@ -10,19 +10,59 @@ pub enum Error {
     /// An error with the local store
     LocalStore(filedb::Error),
     /// Any other error is passed up this way.
-    Internal(Box<dyn std::error::Error>),
+    Internal(Box<dyn std::error::Error + Send + Sync>),
 
     /// The NBMData doesn't have a matching column
     NBMData(nbm_tools::Error),
 
+    /// The server responded to a download request with an HTTP error status.
+    Http {
+        /// The url that was requested.
+        url: String,
+        /// The HTTP status code returned by the server.
+        status: u16,
+    },
+    /// A download failed after it was retried, either because the
+    /// [RetryPolicy](crate::RetryPolicy) gave up or because a later attempt failed with an error
+    /// that isn't worth retrying.
+    RetriesExhausted {
+        /// The number of attempts made.
+        attempts: u32,
+        /// The error from the final attempt.
+        last: Box<Error>,
+    },
+    /// The downloaded content doesn't look like an NBM 1D CSV file, e.g. an HTML error page.
+    InvalidContent {
+        /// The url that was requested.
+        url: String,
+    },
+
     /// No data for that initialization time is available for any location.
     InitializationTimeNotAvailable(chrono::NaiveDateTime),
+    /// The file isn't in the local store and the [NBMStore](crate::NBMStore) is not allowed to
+    /// download it, see [AccessMode::CacheOnly](crate::AccessMode::CacheOnly).
+    NotInLocalArchive {
+        /// The name of the requested file.
+        file_name: String,
+        /// The initialization time of the requested file.
+        init_time: chrono::NaiveDateTime,
+    },
     /// There was no match for the requested site, the internal value is the requested site.
     NoMatch(String),
     /// There were multiple matches for the requested site.
     AmbiguousSite {
-        /// A list of sites that are potential matches.
-        matches: Vec<SiteInfo>,
+        /// A list of sites that are potential matches, best match first.
+        matches: Vec<SiteMatch>,
+    },
+    /// The "locations.csv" file for an initialization time couldn't be loaded, so sites couldn't
+    /// be validated against it. Bulk operations like
+    /// [NBMStore::prefetch()](crate::NBMStore::prefetch) share the cause between every site it
+    /// affected.
+    LocationsUnavailable {
+        /// The initialization time.
+        init_time: chrono::NaiveDateTime,
+        /// Why the file couldn't be loaded.
+        cause: std::sync::Arc<Error>,
     },
 }
 
@@ -40,19 +80,37 @@ impl std::fmt::Display for Error {
             Self::LocalStore(err) => write!(f, "filedb err: {}", err),
             Self::Internal(err) => write!(f, "{}", err),
             Self::NBMData(err) => write!(f, "NBMData err: {}", err),
+            Self::Http { url, status } => write!(f, "HTTP status {} for {}", status, url),
+            Self::RetriesExhausted { attempts, last } => {
+                write!(f, "Gave up after {} attempts: {}", attempts, last)
+            }
+            Self::InvalidContent { url } => write!(f, "Content from {} is not a CSV file", url),
             Self::NoMatch(requested_site) => {
                 write!(f, "No match found for site {}", requested_site)
             }
             Self::AmbiguousSite { matches } => {
                 writeln!(f, "Ambiguous site name, possible matches are")?;
-                for site_info in matches {
-                    writeln!(f, "     {}", site_info)?;
+                for site_match in matches {
+                    writeln!(f, "     {}", site_match)?;
                 }
                 Ok(())
             }
+            Self::NotInLocalArchive {
+                file_name,
+                init_time,
+            } => write!(
+                f,
+                "{} for initialization time {} is not in the local archive",
+                file_name, init_time
+            ),
             Self::InitializationTimeNotAvailable(init_time) => {
                 write!(f, "No data available for initialization time {}", init_time)
             }
+            Self::LocationsUnavailable { init_time, cause } => write!(
+                f,
+                "Couldn't load the locations for initialization time {}: {}",
+                init_time, cause
+            ),
         }
     }
 }
@@ -61,6 +119,8 @@ impl std::error::Error for Error {
     fn cause(&self) -> Option<&dyn std::error::Error> {
         match self {
             Self::Internal(err) => Some(err.as_ref()),
+            Self::RetriesExhausted { last, .. } => Some(last.as_ref()),
+            Self::LocationsUnavailable { cause, .. } => Some(cause.as_ref()),
             _ => None,
         }
     }
@@ -68,6 +128,8 @@ impl std::error::Error for Error {
     fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
         match self {
             Self::Internal(err) => Some(err.as_ref()),
+            Self::RetriesExhausted { last, .. } => Some(last.as_ref()),
+            Self::LocationsUnavailable { cause, .. } => Some(cause.as_ref()),
             _ => None,
         }
     }
@@ -79,6 +141,12 @@ impl From<filedb::Error> for Error {
     }
 }
 
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Self {
+        Self::Internal(err.into())
+    }
+}
+
 impl From<std::string::FromUtf8Error> for Error {
     fn from(err: std::string::FromUtf8Error) -> Self {
         Self::Internal(err.into())
@@ -109,3 +177,10 @@ impl From<csv::Error> for Error {
     }
 }
 
+#[cfg(feature = "async")]
+impl From<tokio::task::JoinError> for Error {
+    fn from(err: tokio::task::JoinError) -> Self {
+        Self::Internal(err.into())
+    }
+}
+
@@ -0,0 +1,135 @@
+use std::str::FromStr;
+
+/// The forecasts for one site from a series of initialization times, useful for seeing how the
+/// forecast for a valid time changed from run to run (dprog/dt).
+#[derive(Debug)]
+pub struct ForecastHistory {
+    /// The site the forecasts are for, as validated against the most recent run.
+    pub site: crate::SiteInfo,
+    /// The runs that were available, oldest first.
+    pub runs: Vec<ForecastRun>,
+    /// The initialization times that couldn't be retrieved, and why.
+    pub failures: Vec<(chrono::NaiveDateTime, crate::Error)>,
+}
+
+/// The forecast from a single initialization time.
+#[derive(Debug)]
+pub struct ForecastRun {
+    /// The initialization time.
+    pub initialization_time: chrono::NaiveDateTime,
+    /// The version of the NBM that produced the forecast.
+    pub nbm_version: crate::NBMVersion,
+    /// The forecast.
+    pub data: nbm_tools::NBMData,
+    /// The valid times in the forecast, in the order they appear in the file.
+    pub valid_times: Vec<chrono::NaiveDateTime>,
+}
+
+impl ForecastRun {
+    /// The time between the initialization time and a valid time.
+    pub fn lead_time(&self, valid_time: chrono::NaiveDateTime) -> chrono::Duration {
+        valid_time - self.initialization_time
+    }
+}
+
+impl ForecastHistory {
+    /// Every valid time in any of the runs, oldest first.
+    pub fn valid_times(&self) -> Vec<chrono::NaiveDateTime> {
+        let mut valid_times: Vec<_> = self
+            .runs
+            .iter()
+            .flat_map(|run| run.valid_times.iter().cloned())
+            .collect();
+
+        valid_times.sort_unstable();
+        valid_times.dedup();
+        valid_times
+    }
+
+    /// All the runs with a forecast for `valid_time` and their lead times, oldest run first.
+    pub fn at_valid_time(
+        &self,
+        valid_time: chrono::NaiveDateTime,
+    ) -> Vec<(&ForecastRun, chrono::Duration)> {
+        self.runs
+            .iter()
+            .filter(|run| run.valid_times.contains(&valid_time))
+            .map(|run| (run, run.lead_time(valid_time)))
+            .collect()
+    }
+
+    /// All the runs with a forecast for a lead time and the valid time it corresponds to, oldest
+    /// run first.
+    pub fn at_lead_time(
+        &self,
+        lead_time: chrono::Duration,
+    ) -> Vec<(&ForecastRun, chrono::NaiveDateTime)> {
+        self.runs
+            .iter()
+            .map(|run| (run, run.initialization_time + lead_time))
+            .filter(|(run, valid_time)| run.valid_times.contains(valid_time))
+            .collect()
+    }
+}
+
+impl crate::NBMStore {
+    /// Retrieve the forecasts for a site from every initialization time in a range.
+    ///
+    /// The site is validated against each run separately, so a run where the site is missing or
+    /// the data isn't available ends up in [ForecastHistory::failures] instead of stopping the
+    /// whole request. It is only an error if none of the runs are available.
+    pub fn forecast_history(
+        &self,
+        site: &str,
+        init_times: std::ops::RangeInclusive<chrono::NaiveDateTime>,
+    ) -> Result<ForecastHistory, crate::Error> {
+        let mut runs = vec![];
+        let mut failures = vec![];
+        let mut latest_site = None;
+
+        for init_time in self.schedule.initialization_times(&init_times) {
+            match self.retrieve_run(site, init_time) {
+                Ok((site_info, run)) => {
+                    latest_site = Some(site_info);
+                    runs.push(run);
+                }
+                Err(err) => failures.push((init_time, err)),
+            }
+        }
+
+        match latest_site {
+            Some(site) => Ok(ForecastHistory {
+                site,
+                runs,
+                failures,
+            }),
+            None => Err(failures
+                .pop()
+                .map(|(_, err)| err)
+                .unwrap_or_else(|| crate::Error::NoMatch(site.to_owned()))),
+        }
+    }
+
+    fn retrieve_run(
+        &self,
+        site: &str,
+        init_time: chrono::NaiveDateTime,
+    ) -> Result<(crate::SiteInfo, ForecastRun), crate::Error> {
+        let validation = self.validate_request(site, init_time)?;
+
+        let (data, _) = self.load_file(&validation.file_name(), init_time, |text| {
+            Ok(nbm_tools::NBMData::from_str(text)?)
+        })?;
+        let valid_times = data.valid_times().iter().cloned().collect();
+
+        Ok((
+            validation.site,
+            ForecastRun {
+                initialization_time: init_time,
+                nbm_version: validation.nbm_version,
+                data,
+                valid_times,
+            },
+        ))
+    }
+}
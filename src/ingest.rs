@@ -0,0 +1,446 @@
+use std::fmt::Display;
+
+/// When to fetch the files for each cycle, see [NBMStore::ingest()](crate::NBMStore::ingest).
+#[derive(Debug, Clone)]
+pub struct IngestConfig {
+    /// How long after the initialization time to make the first attempt. The default is 90
+    /// minutes.
+    pub delay: chrono::Duration,
+    /// How long to wait before trying again for files that weren't available yet. The default is
+    /// 10 minutes.
+    pub retry_interval: chrono::Duration,
+    /// How long after the initialization time to give up on files that still aren't available.
+    /// The default is 4 hours.
+    pub deadline: chrono::Duration,
+}
+
+impl Default for IngestConfig {
+    fn default() -> Self {
+        Self {
+            delay: chrono::Duration::minutes(90),
+            retry_interval: chrono::Duration::minutes(10),
+            deadline: chrono::Duration::hours(4),
+        }
+    }
+}
+
+/// The summary of ingesting a single cycle.
+#[derive(Debug)]
+pub struct CycleReport {
+    /// The initialization time.
+    pub initialization_time: chrono::NaiveDateTime,
+    /// The number of sites requested.
+    pub expected: usize,
+    /// The number of sites now in the local store.
+    pub retrieved: usize,
+    /// How many times the remote archive was checked.
+    pub attempts: u32,
+    /// The sites that never made it into the local store and the error from the last attempt.
+    pub missing: Vec<(String, crate::Error)>,
+}
+
+impl CycleReport {
+    /// Check if every site made it into the local store.
+    pub fn is_complete(&self) -> bool {
+        self.missing.is_empty()
+    }
+}
+
+impl Display for CycleReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        write!(
+            f,
+            "{}: {} of {} sites after {} attempts",
+            self.initialization_time, self.retrieved, self.expected, self.attempts
+        )?;
+
+        for (site, err) in &self.missing {
+            write!(f, "\n  missing {}: {}", site, err)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl crate::NBMStore {
+    /// Keep the local store up to date with the latest cycles, this never returns.
+    ///
+    /// A fixed delay after each initialization time in the [CycleSchedule](crate::CycleSchedule)
+    /// this wakes up and fetches the "locations.csv" file and the files for `sites`, see
+    /// [Self::ingest_cycle()]. The report for each cycle is passed to `on_cycle`, e.g. to log it.
+    ///
+    /// On startup the most recent cycle that is due is fetched right away. After that every cycle
+    /// is fetched in order, so cycles that came due while retrying an earlier one aren't skipped.
+    pub fn ingest<S, F>(&self, sites: &[S], config: &IngestConfig, mut on_cycle: F) -> !
+    where
+        S: AsRef<str>,
+        F: FnMut(&CycleReport),
+    {
+        let clock = SystemClock;
+        let mut last_cycle = None;
+
+        loop {
+            let now = clock.now();
+
+            for init_time in self.cycles_due(last_cycle, now, config.delay) {
+                on_cycle(&self.ingest_cycle_with_clock(sites, init_time, config, &clock));
+                last_cycle = Some(init_time);
+            }
+
+            let next_cycle = self
+                .schedule
+                .next_initialization_time(self.cycle_due(clock.now(), config.delay));
+            clock.sleep_until(next_cycle + config.delay);
+        }
+    }
+
+    /// Fetch the "locations.csv" file and the files for `sites` for a single initialization time.
+    ///
+    /// The "locations.csv" file is always fetched first, even if `sites` is empty. Files that
+    /// aren't available yet are tried again every `retry_interval` until the deadline in the
+    /// [IngestConfig] passes. Sites that don't validate are not retried.
+    pub fn ingest_cycle<S: AsRef<str>>(
+        &self,
+        sites: &[S],
+        init_time: chrono::NaiveDateTime,
+        config: &IngestConfig,
+    ) -> CycleReport {
+        self.ingest_cycle_with_clock(sites, init_time, config, &SystemClock)
+    }
+
+    fn ingest_cycle_with_clock<S: AsRef<str>>(
+        &self,
+        sites: &[S],
+        init_time: chrono::NaiveDateTime,
+        config: &IngestConfig,
+        clock: &dyn Clock,
+    ) -> CycleReport {
+        let deadline = init_time + config.deadline;
+
+        let mut has_locations = false;
+        let mut pending: Vec<String> = sites.iter().map(|site| site.as_ref().to_owned()).collect();
+        let mut failed = vec![];
+        let mut late = vec![];
+        let mut attempts = 0;
+
+        loop {
+            attempts += 1;
+            late.clear();
+
+            // Every site needs the catalog to validate, so get it first.
+            if !has_locations {
+                match self.locations(init_time) {
+                    Ok(_) => has_locations = true,
+                    Err(err) if is_late(&err) => late.push((LOCATIONS.to_owned(), err)),
+                    Err(err) => {
+                        failed.push((LOCATIONS.to_owned(), err));
+                        break;
+                    }
+                }
+            }
+
+            if has_locations {
+                for item in self.prefetch(&pending, init_time..=init_time) {
+                    match item.outcome {
+                        Ok(_) => {}
+                        Err(err) if is_late(&err) => late.push((item.site, err)),
+                        Err(err) => failed.push((item.site, err)),
+                    }
+                }
+
+                pending = late.iter().map(|(site, _)| site.clone()).collect();
+            }
+
+            let retry_at = clock.now() + config.retry_interval;
+            if late.is_empty() || retry_at > deadline {
+                break;
+            }
+
+            clock.sleep_until(retry_at);
+        }
+
+        let mut missing = failed;
+        missing.append(&mut late);
+        if !has_locations {
+            // The sites were never tried, and without a catalog they aren't available either.
+            missing.extend(pending.into_iter().map(|site| {
+                (
+                    site,
+                    crate::Error::InitializationTimeNotAvailable(init_time),
+                )
+            }));
+        }
+
+        let missing_sites = missing.iter().filter(|(file, _)| file != LOCATIONS).count();
+
+        CycleReport {
+            initialization_time: init_time,
+            expected: sites.len(),
+            retrieved: sites.len() - missing_sites,
+            attempts,
+            missing,
+        }
+    }
+
+    /// The cycles to ingest, in order. That is every cycle after `last_cycle` that is due by
+    /// `now`, or only the most recent one if nothing has been ingested yet.
+    fn cycles_due(
+        &self,
+        last_cycle: Option<chrono::NaiveDateTime>,
+        now: chrono::NaiveDateTime,
+        delay: chrono::Duration,
+    ) -> Vec<chrono::NaiveDateTime> {
+        let due = self.cycle_due(now, delay);
+
+        let mut init_time = match last_cycle {
+            Some(last_cycle) => self.schedule.next_initialization_time(last_cycle),
+            None => due,
+        };
+
+        let mut cycles = vec![];
+        while init_time <= due {
+            cycles.push(init_time);
+            init_time = self.schedule.next_initialization_time(init_time);
+        }
+
+        cycles
+    }
+
+    /// The most recent cycle that should be available by `now`.
+    fn cycle_due(
+        &self,
+        now: chrono::NaiveDateTime,
+        delay: chrono::Duration,
+    ) -> chrono::NaiveDateTime {
+        self.schedule.most_recent_initialization_time(now - delay)
+    }
+}
+
+/// The name used for the "locations.csv" file in a [CycleReport].
+const LOCATIONS: &str = "locations.csv";
+
+/// Errors for files that might still show up, as opposed to sites that aren't in the catalog.
+fn is_late(err: &crate::Error) -> bool {
+    if let crate::Error::LocationsUnavailable { cause, .. } = err {
+        return is_late(cause);
+    }
+
+    !matches!(
+        err,
+        crate::Error::NoMatch(_)
+            | crate::Error::AmbiguousSite { .. }
+            | crate::Error::NotInLocalArchive { .. }
+    )
+}
+
+/// Where ingesting gets the time from, so tests don't depend on the real time.
+trait Clock {
+    fn now(&self) -> chrono::NaiveDateTime;
+    fn sleep_until(&self, time: chrono::NaiveDateTime);
+}
+
+struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> chrono::NaiveDateTime {
+        chrono::Utc::now().naive_utc()
+    }
+
+    fn sleep_until(&self, time: chrono::NaiveDateTime) {
+        // A time in the past doesn't convert to a std::time::Duration, and there's no need to wait.
+        if let Ok(wait) = (time - self.now()).to_std() {
+            std::thread::sleep(wait);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Fetcher;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn test_cycle_due() -> Result<(), crate::Error> {
+        let temp_db_file = tempfile::NamedTempFile::new()?;
+        let arch = crate::NBMStore::connect(temp_db_file.path())?;
+
+        let delay = IngestConfig::default().delay;
+
+        let now = chrono::NaiveDate::from_ymd(2021, 2, 28).and_hms(14, 15, 0);
+        let expected = chrono::NaiveDate::from_ymd(2021, 2, 28).and_hms(7, 0, 0);
+        assert_eq!(arch.cycle_due(now, delay), expected);
+
+        let now = chrono::NaiveDate::from_ymd(2021, 2, 28).and_hms(14, 30, 0);
+        let expected = chrono::NaiveDate::from_ymd(2021, 2, 28).and_hms(13, 0, 0);
+        assert_eq!(arch.cycle_due(now, delay), expected);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_cycles_due() -> Result<(), crate::Error> {
+        let temp_db_file = tempfile::NamedTempFile::new()?;
+        let arch = crate::NBMStore::connect(temp_db_file.path())?;
+
+        let delay = IngestConfig::default().delay;
+        let now = chrono::NaiveDate::from_ymd(2021, 2, 28).and_hms(14, 30, 0);
+        let hour = |day, hour| chrono::NaiveDate::from_ymd(2021, 2, day).and_hms(hour, 0, 0);
+
+        assert_eq!(arch.cycles_due(None, now, delay), vec![hour(28, 13)]);
+        assert_eq!(
+            arch.cycles_due(Some(hour(27, 19)), now, delay),
+            vec![hour(28, 1), hour(28, 7), hour(28, 13)]
+        );
+        assert!(arch.cycles_due(Some(hour(28, 13)), now, delay).is_empty());
+
+        Ok(())
+    }
+
+    /// A clock that only moves when it sleeps.
+    struct FakeClock(Arc<Mutex<chrono::NaiveDateTime>>);
+
+    impl Clock for FakeClock {
+        fn now(&self) -> chrono::NaiveDateTime {
+            *self.0.lock().unwrap()
+        }
+
+        fn sleep_until(&self, time: chrono::NaiveDateTime) {
+            *self.0.lock().unwrap() = time;
+        }
+    }
+
+    /// Serves the fixtures once the clock passes `available`, and keeps track of the requests.
+    fn fixtures_from(
+        clock: Arc<Mutex<chrono::NaiveDateTime>>,
+        available: chrono::NaiveDateTime,
+        requests: Arc<Mutex<Vec<String>>>,
+    ) -> impl crate::Fetcher {
+        let replay =
+            crate::ReplayFetcher::new(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures"));
+
+        move |url: &str| {
+            requests.lock().unwrap().push(url.to_owned());
+
+            if *clock.lock().unwrap() < available {
+                Err(crate::Error::Http {
+                    url: url.to_owned(),
+                    status: 404,
+                })
+            } else {
+                replay.fetch(url)
+            }
+        }
+    }
+
+    #[test]
+    fn test_ingest_cycle_past_deadline() -> Result<(), crate::Error> {
+        let init_time = chrono::NaiveDate::from_ymd(2021, 2, 28).and_hms(13, 0, 0);
+        let now = Arc::new(Mutex::new(init_time + chrono::Duration::hours(5)));
+        let requests = Arc::new(Mutex::new(vec![]));
+
+        let temp_db_file = tempfile::NamedTempFile::new()?;
+        let arch = crate::NBMStore::connect(temp_db_file.path())?.with_fetcher(fixtures_from(
+            now.clone(),
+            init_time + chrono::Duration::hours(6),
+            requests,
+        ));
+
+        let report = arch.ingest_cycle_with_clock(
+            &["KMSO", "KGPI"],
+            init_time,
+            &IngestConfig::default(),
+            &FakeClock(now),
+        );
+
+        assert_eq!(report.initialization_time, init_time);
+        assert_eq!(report.expected, 2);
+        assert_eq!(report.retrieved, 0);
+        assert_eq!(report.attempts, 1);
+        assert!(!report.is_complete());
+
+        let missing: Vec<_> = report
+            .missing
+            .iter()
+            .map(|(file, _)| file.as_str())
+            .collect();
+        assert_eq!(missing, vec!["locations.csv", "KMSO", "KGPI"]);
+        for (_, err) in &report.missing {
+            match err {
+                crate::Error::InitializationTimeNotAvailable(_) => {}
+                err => panic!("Unexpected error {}", err),
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_ingest_cycle_retries() -> Result<(), crate::Error> {
+        let init_time = chrono::NaiveDate::from_ymd(2021, 2, 28).and_hms(13, 0, 0);
+        let now = Arc::new(Mutex::new(init_time + chrono::Duration::minutes(90)));
+        let requests = Arc::new(Mutex::new(vec![]));
+
+        let temp_db_file = tempfile::NamedTempFile::new()?;
+        let arch = crate::NBMStore::connect(temp_db_file.path())?.with_fetcher(fixtures_from(
+            now.clone(),
+            init_time + chrono::Duration::hours(2),
+            requests.clone(),
+        ));
+
+        let report = arch.ingest_cycle_with_clock(
+            &["KMSO", "id:KXYZ"],
+            init_time,
+            &IngestConfig::default(),
+            &FakeClock(now.clone()),
+        );
+
+        // Tried at 14:30, 14:40, 14:50 and 15:00.
+        assert_eq!(report.attempts, 4);
+        assert_eq!(*now.lock().unwrap(), init_time + chrono::Duration::hours(2));
+        assert_eq!(report.expected, 2);
+        assert_eq!(report.retrieved, 1);
+        match &report.missing[..] {
+            [(site, crate::Error::NoMatch(_))] => assert_eq!(site, "id:KXYZ"),
+            missing => panic!("Unexpected missing {:?}", missing),
+        }
+
+        let requests = requests.lock().unwrap();
+        assert!(requests[0].ends_with("/2021/02/28/NBM4.0/13/locations.csv"));
+        assert!(requests
+            .last()
+            .unwrap()
+            .ends_with("/2021/02/28/NBM4.0/13/KMSO.csv"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_ingest_cycle_without_sites() -> Result<(), crate::Error> {
+        let init_time = chrono::NaiveDate::from_ymd(2021, 2, 28).and_hms(13, 0, 0);
+        let now = Arc::new(Mutex::new(init_time + chrono::Duration::minutes(90)));
+        let requests = Arc::new(Mutex::new(vec![]));
+
+        let temp_db_file = tempfile::NamedTempFile::new()?;
+        let arch = crate::NBMStore::connect(temp_db_file.path())?.with_fetcher(fixtures_from(
+            now.clone(),
+            init_time,
+            requests.clone(),
+        ));
+
+        let sites: &[&str] = &[];
+        let report = arch.ingest_cycle_with_clock(
+            sites,
+            init_time,
+            &IngestConfig::default(),
+            &FakeClock(now),
+        );
+
+        assert!(report.is_complete());
+        assert_eq!(report.attempts, 1);
+        assert_eq!(requests.lock().unwrap().len(), 1);
+        assert!(arch.metadata.locations(init_time)?.is_some());
+
+        Ok(())
+    }
+}
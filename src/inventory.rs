@@ -0,0 +1,208 @@
+use rusqlite::ToSql;
+
+/// An initialization time where some of the requested sites aren't in the local store, see
+/// [NBMStore::find_gaps()](crate::NBMStore::find_gaps).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Gap {
+    /// The initialization time.
+    pub initialization_time: chrono::NaiveDateTime,
+    /// The ids of the sites that are missing.
+    pub missing: Vec<String>,
+}
+
+impl crate::NBMStore {
+    /// List every file in the local store, sorted by initialization time and file name.
+    pub fn stored_files(&self) -> Result<Vec<crate::StoredFile>, crate::Error> {
+        self.metadata.stored_files()
+    }
+
+    /// The number of files in the local store for each initialization time, oldest first.
+    pub fn count_by_initialization_time(
+        &self,
+    ) -> Result<Vec<(chrono::NaiveDateTime, usize)>, crate::Error> {
+        const QUERY_COUNTS: &'static str = r#"
+            SELECT init_time, COUNT(*) FROM nbmarch_files GROUP BY init_time ORDER BY init_time
+        "#;
+
+        let mut stmt = self.metadata.conn.prepare(QUERY_COUNTS)?;
+        let temp = stmt.query_map(rusqlite::NO_PARAMS, |row| {
+            let init_time: i64 = row.get(0)?;
+            let count: i64 = row.get(1)?;
+            Ok((
+                chrono::NaiveDateTime::from_timestamp(init_time, 0),
+                count as usize,
+            ))
+        })?;
+
+        Ok(temp.filter_map(|res| res.ok()).collect())
+    }
+
+    /// The number of initialization times in the local store for each site, sorted by site id.
+    pub fn count_by_site(&self) -> Result<Vec<(String, usize)>, crate::Error> {
+        const QUERY_COUNTS: &'static str = r#"
+            SELECT file_name, COUNT(*) FROM nbmarch_files
+            WHERE file_name != 'locations.csv'
+            GROUP BY file_name ORDER BY file_name
+        "#;
+
+        let mut stmt = self.metadata.conn.prepare(QUERY_COUNTS)?;
+        let temp = stmt.query_map(rusqlite::NO_PARAMS, |row| {
+            let file_name: String = row.get(0)?;
+            let count: i64 = row.get(1)?;
+            Ok((site_id(&file_name).to_owned(), count as usize))
+        })?;
+
+        Ok(temp.filter_map(|res| res.ok()).collect())
+    }
+
+    /// The total size in bytes of all the files in the local store.
+    pub fn total_bytes(&self) -> Result<u64, crate::Error> {
+        const QUERY_TOTAL: &'static str = r#"
+            SELECT COALESCE(SUM(size), 0) FROM nbmarch_files
+        "#;
+
+        let total: i64 = self
+            .metadata
+            .conn
+            .query_row(QUERY_TOTAL, rusqlite::NO_PARAMS, |row| row.get(0))?;
+
+        Ok(total as u64)
+    }
+
+    /// Find the initialization times in a range where some of the sites aren't in the local store.
+    ///
+    /// The sites are site ids. Initialization times with nothing in the local store are gaps for
+    /// every site.
+    pub fn find_gaps<S: AsRef<str>>(
+        &self,
+        sites: &[S],
+        init_times: std::ops::RangeInclusive<chrono::NaiveDateTime>,
+    ) -> Result<Vec<Gap>, crate::Error> {
+        const QUERY_FILES: &'static str = r#"
+            SELECT file_name FROM nbmarch_files WHERE init_time = ?
+        "#;
+
+        let mut stmt = self.metadata.conn.prepare(QUERY_FILES)?;
+
+        let mut gaps = vec![];
+        for init_time in self.schedule.initialization_times(&init_times) {
+            let stored: std::collections::HashSet<String> = stmt
+                .query_map(&[&init_time.timestamp() as &dyn ToSql], |row| {
+                    row.get::<_, String>(0)
+                })?
+                .filter_map(|res| res.ok())
+                .map(|file_name| site_id(&file_name).to_uppercase())
+                .collect();
+
+            let missing: Vec<String> = sites
+                .iter()
+                .map(|site| site.as_ref().trim().to_uppercase())
+                .filter(|site| !stored.contains(site))
+                .collect();
+
+            if !missing.is_empty() {
+                gaps.push(Gap {
+                    initialization_time: init_time,
+                    missing,
+                });
+            }
+        }
+
+        Ok(gaps)
+    }
+}
+
+fn site_id(file_name: &str) -> &str {
+    file_name.trim_end_matches(".csv")
+}
+
+#[cfg(test)]
+mod test {
+    #[test]
+    fn test_inventory() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_db_file = tempfile::NamedTempFile::new()?;
+        let arch = crate::NBMStore::connect(temp_db_file.path())?;
+
+        let first = chrono::NaiveDate::from_ymd(2021, 2, 28).and_hms(7, 0, 0);
+        let second = chrono::NaiveDate::from_ymd(2021, 2, 28).and_hms(13, 0, 0);
+
+        for &(file_name, init_time, size) in &[
+            ("locations.csv", first, 1000),
+            ("KMSO.csv", first, 200),
+            ("KGPI.csv", first, 150),
+            ("locations.csv", second, 1000),
+            ("KMSO.csv", second, 250),
+        ] {
+            let provenance =
+                crate::Provenance::unknown(file_name, init_time, arch.nbm_version(init_time));
+            arch.metadata
+                .add_file(file_name, init_time, &provenance, size)?;
+        }
+
+        assert_eq!(arch.stored_files()?.len(), 5);
+        assert_eq!(arch.total_bytes()?, 2600);
+        assert_eq!(
+            arch.count_by_initialization_time()?,
+            vec![(first, 3), (second, 2)]
+        );
+        assert_eq!(
+            arch.count_by_site()?,
+            vec![("KGPI".to_owned(), 1), ("KMSO".to_owned(), 2)]
+        );
+
+        let start = chrono::NaiveDate::from_ymd(2021, 2, 28).and_hms(7, 0, 0);
+        let end = chrono::NaiveDate::from_ymd(2021, 2, 28).and_hms(19, 0, 0);
+        let gaps = arch.find_gaps(&["KMSO", "kgpi"], start..=end)?;
+        assert_eq!(
+            gaps,
+            vec![
+                super::Gap {
+                    initialization_time: second,
+                    missing: vec!["KGPI".to_owned()],
+                },
+                super::Gap {
+                    initialization_time: end,
+                    missing: vec!["KMSO".to_owned(), "KGPI".to_owned()],
+                },
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_inventory_of_existing_local_store() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_db_file = tempfile::NamedTempFile::new()?;
+        let init_time = chrono::NaiveDate::from_ymd(2021, 2, 28).and_hms(13, 0, 0);
+
+        {
+            let local_store = filedb::FileDB::connect(temp_db_file.path())?;
+            local_store.add_file("locations.csv", init_time, &[b'x'; 1000])?;
+            local_store.add_file("KMSO.csv", init_time, &[b'x'; 200])?;
+        }
+
+        let arch = crate::NBMStore::connect(temp_db_file.path())?;
+
+        assert_eq!(
+            arch.stored_files()?,
+            vec![
+                crate::StoredFile {
+                    file_name: "KMSO.csv".to_owned(),
+                    init_time,
+                    size: 200,
+                },
+                crate::StoredFile {
+                    file_name: "locations.csv".to_owned(),
+                    init_time,
+                    size: 1000,
+                },
+            ]
+        );
+        assert_eq!(arch.total_bytes()?, 1200);
+        assert_eq!(arch.count_by_site()?, vec![("KMSO".to_owned(), 1)]);
+
+        assert!(arch.metadata.provenance("KMSO.csv", init_time)?.is_none());
+
+        Ok(())
+    }
+}
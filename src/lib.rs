@@ -1,17 +1,57 @@
 #![warn(missing_docs)]
 /*! An archive of NBM 1D viewer text files organized by initialization time and site.
 
+# Features
+ - `async` adds [AsyncNBMStore], an async version of [NBMStore] for use with tokio.
+ - `serve` adds [Server], an HTTP server for an [NBMStore].
 */
 /* ------------------------------------------------------------------------------------------------
  *                                         Public API
  * --------------------------------------------------------------------------------------------- */
+#[cfg(feature = "async")]
+pub use crate::async_store::{AsyncFetcher, AsyncNBMStore, FetchFuture};
+pub use crate::bundle::{ExportFilter, ImportReport};
+pub use crate::catalog_diff::CatalogDiff;
+pub use crate::download::{is_transient, Fetcher, ReqwestFetcher, RetryPolicy, BASE_URL};
 pub use crate::error::Error;
-pub use crate::nbm_store::NBMStore;
-pub use crate::site_validation::{SiteInfo, SiteValidation};
+pub use crate::history::{ForecastHistory, ForecastRun};
+pub use crate::ingest::{CycleReport, IngestConfig};
+pub use crate::inventory::Gap;
+pub use crate::metadata::StoredFile;
+pub use crate::nbm_store::{AccessMode, NBMStore};
+pub use crate::prefetch::{PrefetchItem, PrefetchStatus};
+pub use crate::provenance::{Provenance, Retrieved, Source};
+pub use crate::replay::ReplayFetcher;
+pub use crate::retention::{PruneReport, RetentionPolicy};
+pub use crate::schedule::CycleSchedule;
+#[cfg(feature = "serve")]
+pub use crate::server::Server;
+pub use crate::site_validation::{BoundingBox, SiteInfo, SiteMatch, SiteValidation};
+pub use crate::version::{NBMVersion, VersionTable};
 /* ------------------------------------------------------------------------------------------------
  *                                        Private Modules
  * --------------------------------------------------------------------------------------------- */
+mod aliases;
+#[cfg(feature = "async")]
+mod async_store;
+mod bundle;
+mod cache;
+mod catalog_diff;
 mod download;
 mod error;
+mod history;
+mod ingest;
+mod inventory;
+mod metadata;
 mod nbm_store;
+mod prefetch;
+mod provenance;
+mod ranking;
+mod replay;
+mod retention;
+mod schedule;
+#[cfg(feature = "serve")]
+mod server;
+mod site_query;
 mod site_validation;
+mod version;
@@ -0,0 +1,505 @@
+//! A command line interface to an [NBMStore](nbmarch::NBMStore).
+use std::collections::{HashMap, HashSet};
+
+const USAGE: &str = "\
+Usage: nbmarch [--store PATH] <COMMAND> [ARGS]
+
+Commands:
+  fetch <SITE> [--time TIME] [--csv]     Retrieve a file and print a summary, or the CSV text
+  sites search <QUERY> [--time TIME]     Rank the sites that match a query
+  prefetch --sites-file FILE --from TIME --to TIME
+                                         Download the files for the sites listed in FILE
+  ingest --sites-file FILE [--delay-minutes N] [--retry-minutes N] [--deadline-hours N]
+                                         Fetch the sites listed in FILE after every cycle
+  ls                                     Count the files for each initialization time
+  prune [--max-age-days N] [--max-bytes N] [--keep-cycles H,H,...]
+                                         Remove old files from the local store
+  export <BUNDLE> [--sites ID,ID,...] [--from TIME] [--to TIME]
+                                         Write files to a bundle for another store
+  import <BUNDLE>                        Add the files in a bundle to the local store
+  serve [--addr ADDR] [--threads N]      Run an HTTP server over the local store, needs the serve
+                                         feature, the default address is 127.0.0.1:8080
+
+Options:
+  --store PATH   The local store to use instead of the default
+  -h, --help     Print this message
+
+Times are UTC, e.g. 2021-02-28T13:00, 2021-02-28 13:00, 2021022813 or 2021-02-28, and default
+to now.";
+
+/// Options that take a value.
+const OPTIONS: &[&str] = &[
+    "--store",
+    "--time",
+    "--sites-file",
+    "--from",
+    "--to",
+    "--max-age-days",
+    "--max-bytes",
+    "--keep-cycles",
+    "--sites",
+    "--delay-minutes",
+    "--retry-minutes",
+    "--deadline-hours",
+    "--addr",
+    "--threads",
+];
+
+/// Options that don't take a value.
+const FLAGS: &[&str] = &["--csv", "--help", "-h"];
+
+fn main() {
+    let args = match Args::parse(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(msg) => {
+            eprintln!("{}\n\n{}", msg, USAGE);
+            std::process::exit(2);
+        }
+    };
+
+    if args.flag("--help") || args.flag("-h") || args.positional.is_empty() {
+        println!("{}", USAGE);
+        return;
+    }
+
+    if let Err(err) = run(&args) {
+        eprintln!("Error: {}", err);
+        std::process::exit(1);
+    }
+}
+
+fn run(args: &Args) -> Result<(), Box<dyn std::error::Error>> {
+    let store_path = args.option("--store").map(std::path::Path::new);
+
+    let command: Vec<&str> = args.positional.iter().map(String::as_str).collect();
+    match command[..] {
+        ["fetch", site] => fetch(store_path, site, args),
+        ["sites", "search", ref query @ ..] if !query.is_empty() => {
+            search(store_path, &query.join(" "), args)
+        }
+        ["prefetch"] => prefetch(store_path, args),
+        ["ingest"] => ingest(store_path, args),
+        ["ls"] => ls(store_path),
+        ["prune"] => prune(store_path, args),
+        ["export", bundle] => export(store_path, bundle, args),
+        ["import", bundle] => import(store_path, bundle),
+        #[cfg(feature = "serve")]
+        ["serve"] => serve(store_path, args),
+        _ => Err(format!("Unknown command: {}\n\n{}", command.join(" "), USAGE).into()),
+    }
+}
+
+fn fetch(
+    store_path: Option<&std::path::Path>,
+    site: &str,
+    args: &Args,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let arch = nbmarch::NBMStore::connect(store_path)?;
+    let validation = arch.validate_request(site, args.time("--time")?.unwrap_or_else(now))?;
+
+    if args.flag("--csv") {
+        print!("{}", arch.retrieve_csv(validation)?);
+        return Ok(());
+    }
+
+    let site = validation.site.clone();
+    let init_time = validation.initialization_time;
+    let retrieved = arch.retrieve_with_provenance(validation.clone())?;
+    // It's in the local store now, so this doesn't download it again.
+    let summary = summarize(&arch.retrieve_csv(validation)?)?;
+
+    println!("Site:                {}", site);
+    println!("Initialization time: {}", init_time);
+    println!("NBM version:         {}", retrieved.provenance.nbm_version);
+    println!("Source:              {:?}", retrieved.source);
+    println!("URL:                 {}", retrieved.provenance.url);
+    match retrieved.provenance.downloaded {
+        Some(downloaded) => println!("Downloaded:          {}", downloaded),
+        None => println!("Downloaded:          unknown"),
+    }
+    match summary.valid_times {
+        Some((first, last)) => println!("Valid times:         {} to {}", first, last),
+        None => println!("Valid times:         none"),
+    }
+    println!("Rows:                {}", summary.rows);
+    println!("Columns:");
+    for column in &summary.columns {
+        println!("  {}", column);
+    }
+
+    Ok(())
+}
+
+/// What's in an NBM 1D text file.
+#[derive(Debug, PartialEq)]
+struct Summary {
+    columns: Vec<String>,
+    rows: usize,
+    valid_times: Option<(chrono::NaiveDateTime, chrono::NaiveDateTime)>,
+}
+
+/// The valid times are in the first column, as seconds since the Unix epoch.
+fn summarize(text: &str) -> Result<Summary, Box<dyn std::error::Error>> {
+    let mut rdr = csv::Reader::from_reader(text.as_bytes());
+
+    let columns = rdr.headers()?.iter().map(str::to_owned).collect();
+
+    let mut rows = 0;
+    let mut valid_times = None;
+    for rec in rdr.records() {
+        rows += 1;
+
+        let valid_time = match rec?.get(0).and_then(|val| val.trim().parse::<i64>().ok()) {
+            Some(timestamp) => chrono::NaiveDateTime::from_timestamp(timestamp, 0),
+            None => continue,
+        };
+
+        valid_times = match valid_times {
+            Some((first, last)) => Some((
+                std::cmp::min(first, valid_time),
+                std::cmp::max(last, valid_time),
+            )),
+            None => Some((valid_time, valid_time)),
+        };
+    }
+
+    Ok(Summary {
+        columns,
+        rows,
+        valid_times,
+    })
+}
+
+fn search(
+    store_path: Option<&std::path::Path>,
+    query: &str,
+    args: &Args,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let arch = nbmarch::NBMStore::connect(store_path)?;
+
+    for site_match in arch.search_sites(query, args.time("--time")?.unwrap_or_else(now))? {
+        println!("{}", site_match);
+    }
+
+    Ok(())
+}
+
+fn prefetch(
+    store_path: Option<&std::path::Path>,
+    args: &Args,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let sites_file = args.required("--sites-file")?;
+    let from = args.time("--from")?.ok_or("Missing option --from")?;
+    let to = args.time("--to")?.unwrap_or_else(now);
+
+    let sites = read_sites_file(&std::fs::read_to_string(sites_file)?);
+
+    let arch = nbmarch::NBMStore::connect(store_path)?;
+    let report = arch.prefetch(&sites, from..=to);
+
+    let mut failures = 0;
+    for item in &report {
+        match &item.outcome {
+            Ok(status) => println!("{} {:8} {:?}", item.initialization_time, item.site, status),
+            Err(err) => {
+                failures += 1;
+                println!("{} {:8} {}", item.initialization_time, item.site, err);
+            }
+        }
+    }
+
+    println!(
+        "{} of {} files retrieved",
+        report.len() - failures,
+        report.len()
+    );
+
+    Ok(())
+}
+
+fn ingest(
+    store_path: Option<&std::path::Path>,
+    args: &Args,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let sites = read_sites_file(&std::fs::read_to_string(args.required("--sites-file")?)?);
+
+    let mut config = nbmarch::IngestConfig::default();
+    if let Some(minutes) = args.option("--delay-minutes") {
+        config.delay = chrono::Duration::minutes(minutes.parse()?);
+    }
+    if let Some(minutes) = args.option("--retry-minutes") {
+        config.retry_interval = chrono::Duration::minutes(minutes.parse()?);
+    }
+    if let Some(hours) = args.option("--deadline-hours") {
+        config.deadline = chrono::Duration::hours(hours.parse()?);
+    }
+
+    let arch = nbmarch::NBMStore::connect(store_path)?;
+    arch.ingest(&sites, &config, |report| {
+        println!("[{}] {}", now().format("%Y-%m-%d %H:%M:%S"), report)
+    })
+}
+
+fn ls(store_path: Option<&std::path::Path>) -> Result<(), Box<dyn std::error::Error>> {
+    let arch = nbmarch::NBMStore::connect(store_path)?;
+
+    for (init_time, count) in arch.count_by_initialization_time()? {
+        println!("{} {:6} files", init_time, count);
+    }
+    println!("Total size: {} bytes", arch.total_bytes()?);
+
+    Ok(())
+}
+
+fn prune(
+    store_path: Option<&std::path::Path>,
+    args: &Args,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let policy = nbmarch::RetentionPolicy {
+        max_age: args
+            .option("--max-age-days")
+            .map(str::parse)
+            .transpose()?
+            .map(chrono::Duration::days),
+        max_total_bytes: args.option("--max-bytes").map(str::parse).transpose()?,
+        keep_cycles: args
+            .option("--keep-cycles")
+            .map(|hours| hours.split(',').map(|hour| hour.trim().parse()).collect())
+            .transpose()?,
+    };
+
+    let arch = nbmarch::NBMStore::connect(store_path)?.with_retention_policy(policy);
+    let report = arch.prune()?;
+
+    println!(
+        "Removed {} files, {} bytes",
+        report.files_removed, report.bytes_removed
+    );
+
+    Ok(())
+}
+
+fn export(
+    store_path: Option<&std::path::Path>,
+    bundle: &str,
+    args: &Args,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let from = args.time("--from")?;
+    let to = args.time("--to")?;
+
+    let filter = nbmarch::ExportFilter {
+        sites: args.option("--sites").map(|sites| {
+            sites
+                .split(',')
+                .map(|site| site.trim().to_owned())
+                .collect()
+        }),
+        init_times: match (from, to) {
+            (None, None) => None,
+            (from, to) => Some(
+                from.unwrap_or_else(|| chrono::NaiveDate::from_ymd(1970, 1, 1).and_hms(0, 0, 0))
+                    ..=to.unwrap_or_else(now),
+            ),
+        },
+    };
+
+    let arch = nbmarch::NBMStore::connect(store_path)?;
+    let count = arch.export(&filter, std::path::Path::new(bundle))?;
+
+    println!("Exported {} files to {}", count, bundle);
+
+    Ok(())
+}
+
+fn import(
+    store_path: Option<&std::path::Path>,
+    bundle: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let arch = nbmarch::NBMStore::connect(store_path)?;
+    let report = arch.import(std::path::Path::new(bundle))?;
+
+    println!(
+        "Imported {} files, skipped {} duplicates",
+        report.imported, report.duplicates
+    );
+    for (file_name, init_time) in &report.conflicts {
+        println!("Kept the local copy of {} for {}", file_name, init_time);
+    }
+    for (file_name, init_time) in &report.invalid {
+        println!("Skipped invalid {} for {}", file_name, init_time);
+    }
+
+    Ok(())
+}
+
+#[cfg(feature = "serve")]
+fn serve(
+    store_path: Option<&std::path::Path>,
+    args: &Args,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let addr = args.option("--addr").unwrap_or("127.0.0.1:8080");
+    let threads = args.option("--threads").map(str::parse).transpose()?;
+
+    let store_path = store_path.map(std::path::Path::to_owned);
+    let mut server =
+        nbmarch::Server::new(move || nbmarch::NBMStore::connect(store_path.as_deref()));
+    if let Some(threads) = threads {
+        server = server.with_threads(threads);
+    }
+
+    println!("Serving on http://{}", addr);
+    server.run(addr)?;
+
+    Ok(())
+}
+
+/// The sites in a sites file, one per line. Blank lines and lines starting with '#' are skipped.
+fn read_sites_file(text: &str) -> Vec<String> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_owned)
+        .collect()
+}
+
+fn now() -> chrono::NaiveDateTime {
+    chrono::Utc::now().naive_utc()
+}
+
+fn parse_time(val: &str) -> Result<chrono::NaiveDateTime, String> {
+    const FORMATS: &[&str] = &["%Y-%m-%dT%H:%M", "%Y-%m-%d %H:%M", "%Y-%m-%dT%H:%M:%S"];
+
+    FORMATS
+        .iter()
+        .find_map(|fmt| chrono::NaiveDateTime::parse_from_str(val, fmt).ok())
+        .or_else(|| chrono::NaiveDateTime::parse_from_str(&format!("{}00", val), "%Y%m%d%H%M").ok())
+        .or_else(|| {
+            chrono::NaiveDate::parse_from_str(val, "%Y-%m-%d")
+                .ok()
+                .map(|date| date.and_hms(0, 0, 0))
+        })
+        .ok_or_else(|| format!("Invalid time: {}", val))
+}
+
+/// The command line broken into positional arguments, options with values, and flags.
+#[derive(Debug, Default)]
+struct Args {
+    positional: Vec<String>,
+    options: HashMap<String, String>,
+    flags: HashSet<String>,
+}
+
+impl Args {
+    fn parse<I: Iterator<Item = String>>(mut args: I) -> Result<Self, String> {
+        let mut parsed = Self::default();
+
+        while let Some(arg) = args.next() {
+            if OPTIONS.contains(&arg.as_str()) {
+                let value = args
+                    .next()
+                    .ok_or_else(|| format!("Missing value for {}", arg))?;
+                parsed.options.insert(arg, value);
+            } else if FLAGS.contains(&arg.as_str()) {
+                parsed.flags.insert(arg);
+            } else if arg.starts_with('-') && arg.len() > 1 {
+                return Err(format!("Unknown option: {}", arg));
+            } else {
+                parsed.positional.push(arg);
+            }
+        }
+
+        Ok(parsed)
+    }
+
+    fn option(&self, name: &str) -> Option<&str> {
+        self.options.get(name).map(String::as_str)
+    }
+
+    fn required(&self, name: &str) -> Result<&str, String> {
+        self.option(name)
+            .ok_or_else(|| format!("Missing option {}", name))
+    }
+
+    fn flag(&self, name: &str) -> bool {
+        self.flags.contains(name)
+    }
+
+    fn time(&self, name: &str) -> Result<Option<chrono::NaiveDateTime>, String> {
+        self.option(name).map(parse_time).transpose()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn args(line: &str) -> Result<Args, String> {
+        Args::parse(line.split_whitespace().map(str::to_owned))
+    }
+
+    #[test]
+    fn test_parse_args() -> Result<(), String> {
+        let parsed = args("--store /tmp/nbm.db fetch kmso --time 2021022813 --csv")?;
+        assert_eq!(parsed.positional, vec!["fetch", "kmso"]);
+        assert_eq!(parsed.option("--store"), Some("/tmp/nbm.db"));
+        assert!(parsed.flag("--csv"));
+        assert_eq!(
+            parsed.time("--time")?,
+            Some(chrono::NaiveDate::from_ymd(2021, 2, 28).and_hms(13, 0, 0))
+        );
+
+        assert!(args("fetch kmso --time").is_err());
+        assert!(args("ls --bogus").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_time() {
+        let expected = chrono::NaiveDate::from_ymd(2021, 2, 28).and_hms(13, 0, 0);
+        assert_eq!(parse_time("2021-02-28T13:00"), Ok(expected));
+        assert_eq!(parse_time("2021-02-28 13:00"), Ok(expected));
+        assert_eq!(parse_time("2021022813"), Ok(expected));
+        assert_eq!(
+            parse_time("2021-02-28"),
+            Ok(chrono::NaiveDate::from_ymd(2021, 2, 28).and_hms(0, 0, 0))
+        );
+        assert!(parse_time("yesterday").is_err());
+    }
+
+    #[test]
+    fn test_summarize() -> Result<(), Box<dyn std::error::Error>> {
+        let text = include_str!(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/fixtures/2021/02/28/NBM4.0/13/KMSO.csv"
+        ));
+
+        let summary = summarize(text)?;
+        assert_eq!(
+            summary.columns,
+            vec![
+                "validTime",
+                "TMP_2 m above ground",
+                "DPT_2 m above ground",
+                "WSPD_10 m above ground",
+                "APCP1hr_surface"
+            ]
+        );
+        assert_eq!(summary.rows, 36);
+        assert_eq!(
+            summary.valid_times,
+            Some((
+                chrono::NaiveDate::from_ymd(2021, 2, 28).and_hms(14, 0, 0),
+                chrono::NaiveDate::from_ymd(2021, 3, 2).and_hms(1, 0, 0)
+            ))
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_sites_file() {
+        let text = "# Montana\nKMSO\n\n  KGPI  \n";
+        assert_eq!(read_sites_file(text), vec!["KMSO", "KGPI"]);
+    }
+}
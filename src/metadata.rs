@@ -0,0 +1,336 @@
+use rusqlite::{OptionalExtension, ToSql};
+
+/// Bookkeeping about the files in the local store.
+///
+/// This lives in its own tables in the same database file as the local store, so the two always
+/// travel together.
+pub(crate) struct Metadata {
+    pub(crate) conn: rusqlite::Connection,
+}
+
+impl Metadata {
+    pub(crate) fn connect(path: &std::path::Path) -> Result<Self, crate::Error> {
+        const INIT_METADATA_DB: &'static str = r#"
+          CREATE TABLE IF NOT EXISTS nbmarch_files (
+            file_name    TEXT    NOT NULL,
+            init_time    INTEGER NOT NULL,
+            url          TEXT    NOT NULL,
+            downloaded   INTEGER NOT NULL,
+            version_name TEXT    NOT NULL,
+            version_path TEXT    NOT NULL,
+            size         INTEGER NOT NULL,
+            PRIMARY KEY (file_name, init_time));
+
+          CREATE TABLE IF NOT EXISTS nbmarch_sites (
+            catalog INTEGER NOT NULL,
+            id      TEXT    NOT NULL,
+            name    TEXT    NOT NULL,
+            state   TEXT    NOT NULL,
+            lat     REAL    NOT NULL,
+            lon     REAL    NOT NULL,
+            PRIMARY KEY (catalog, id) ON CONFLICT IGNORE);
+
+          CREATE TABLE IF NOT EXISTS nbmarch_site_catalogs (
+            init_time INTEGER PRIMARY KEY,
+            catalog   INTEGER NOT NULL);
+
+          CREATE TABLE IF NOT EXISTS nbmarch_aliases (
+            alias   TEXT PRIMARY KEY COLLATE NOCASE,
+            site_id TEXT NOT NULL);
+
+          CREATE TABLE IF NOT EXISTS nbmarch_state (
+            key   TEXT PRIMARY KEY,
+            value INTEGER NOT NULL);
+        "#;
+
+        let conn = rusqlite::Connection::open(path)?;
+        conn.execute_batch(INIT_METADATA_DB)?;
+
+        Ok(Self { conn })
+    }
+
+    /// Remember where a file in the local store came from.
+    pub(crate) fn add_file(
+        &self,
+        file_name: &str,
+        init_time: chrono::NaiveDateTime,
+        provenance: &crate::Provenance,
+        size: usize,
+    ) -> Result<(), crate::Error> {
+        const INSERT_FILE: &'static str = r#"
+          INSERT OR REPLACE INTO nbmarch_files
+            (file_name, init_time, url, downloaded, version_name, version_path, size)
+          VALUES (?, ?, ?, ?, ?, ?, ?)
+        "#;
+
+        let downloaded = provenance.downloaded.map(|dt| dt.timestamp()).unwrap_or(0);
+
+        self.conn.execute(
+            INSERT_FILE,
+            &[
+                &file_name as &dyn ToSql,
+                &init_time.timestamp(),
+                &provenance.url,
+                &downloaded,
+                &provenance.nbm_version.name,
+                &provenance.nbm_version.path,
+                &(size as i64),
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    /// Record the files in the local store that aren't in `nbmarch_files` yet, i.e. those added
+    /// before this was tracked. They are saved with an empty url, meaning no provenance.
+    ///
+    /// Everything added since goes through [Self::add_file()], so this only runs once per
+    /// database, a row in `nbmarch_state` marks that it's done.
+    pub(crate) fn backfill(&self) -> Result<(), crate::Error> {
+        const QUERY_BACKFILLED: &'static str = r#"
+          SELECT EXISTS (SELECT 1 FROM nbmarch_state WHERE key = 'backfilled')
+        "#;
+
+        const BACKFILL: &'static str = r#"
+          BEGIN TRANSACTION;
+
+          INSERT OR IGNORE INTO nbmarch_files
+            (file_name, init_time, url, downloaded, version_name, version_path, size)
+          SELECT file_name, init_time, '', 0, '', '', length(data) FROM files;
+
+          INSERT OR REPLACE INTO nbmarch_state (key, value) VALUES ('backfilled', 1);
+
+          COMMIT;
+        "#;
+
+        let backfilled: bool =
+            self.conn
+                .query_row(QUERY_BACKFILLED, rusqlite::NO_PARAMS, |row| row.get(0))?;
+
+        if !backfilled {
+            self.check_local_store_layout()?;
+            self.conn.execute_batch(BACKFILL)?;
+        }
+
+        Ok(())
+    }
+
+    /// Make sure FileDB's table is `files (file_name TEXT, init_time INTEGER, data BLOB)`, with the
+    /// initialization times as seconds since the Unix epoch.
+    fn check_local_store_layout(&self) -> Result<(), crate::Error> {
+        const EXPECTED_COLUMNS: &[(&str, &str)] = &[
+            ("file_name", "TEXT"),
+            ("init_time", "INTEGER"),
+            ("data", "BLOB"),
+        ];
+
+        const QUERY_BAD_TIMES: &'static str = r#"
+          SELECT COUNT(*) FROM files WHERE typeof(init_time) != 'integer'
+        "#;
+
+        let columns: Vec<(String, String)> = {
+            let mut stmt = self.conn.prepare("PRAGMA table_info(files)")?;
+            let temp = stmt.query_map(rusqlite::NO_PARAMS, |row| Ok((row.get(1)?, row.get(2)?)))?;
+            temp.collect::<Result<_, _>>()?
+        };
+
+        let matches = EXPECTED_COLUMNS.iter().all(|(name, col_type)| {
+            columns
+                .iter()
+                .any(|(n, t)| n == name && t.eq_ignore_ascii_case(col_type))
+        });
+
+        let bad_times: i64 = if matches {
+            self.conn
+                .query_row(QUERY_BAD_TIMES, rusqlite::NO_PARAMS, |row| row.get(0))?
+        } else {
+            0
+        };
+
+        if !matches || bad_times > 0 {
+            return Err(crate::Error::general_error(format!(
+                "Unexpected FileDB layout, expected table files {:?}, found {:?}",
+                EXPECTED_COLUMNS, columns
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Look up where a file in the local store came from. Files added to the local store before
+    /// this was tracked have no provenance.
+    pub(crate) fn provenance(
+        &self,
+        file_name: &str,
+        init_time: chrono::NaiveDateTime,
+    ) -> Result<Option<crate::Provenance>, crate::Error> {
+        const QUERY_FILE: &'static str = r#"
+          SELECT url, downloaded, version_name, version_path FROM nbmarch_files
+          WHERE file_name = ? AND init_time = ? AND url != ''
+        "#;
+
+        Ok(self
+            .conn
+            .query_row(
+                QUERY_FILE,
+                &[&file_name as &dyn ToSql, &init_time.timestamp()],
+                |row| {
+                    let url = row.get(0)?;
+                    let downloaded: i64 = row.get(1)?;
+                    let version_name: String = row.get(2)?;
+                    let version_path: String = row.get(3)?;
+
+                    Ok(crate::Provenance {
+                        url,
+                        downloaded: from_timestamp(downloaded),
+                        nbm_version: crate::NBMVersion::new(&version_name, &version_path),
+                    })
+                },
+            )
+            .optional()?)
+    }
+
+    /// All the files recorded in the local store, sorted by initialization time and file name.
+    pub(crate) fn stored_files(&self) -> Result<Vec<StoredFile>, crate::Error> {
+        const QUERY_FILES: &'static str = r#"
+          SELECT file_name, init_time, size FROM nbmarch_files ORDER BY init_time, file_name
+        "#;
+
+        let mut stmt = self.conn.prepare(QUERY_FILES)?;
+        let temp = stmt.query_map(rusqlite::NO_PARAMS, |row| {
+            let file_name = row.get(0)?;
+            let init_time: i64 = row.get(1)?;
+            let size: i64 = row.get(2)?;
+
+            Ok(StoredFile {
+                file_name,
+                init_time: chrono::NaiveDateTime::from_timestamp(init_time, 0),
+                size: size as u64,
+            })
+        })?;
+
+        Ok(temp.filter_map(|res| res.ok()).collect())
+    }
+
+    /// Forget about a file that was removed from the local store.
+    pub(crate) fn remove_file(
+        &self,
+        file_name: &str,
+        init_time: chrono::NaiveDateTime,
+    ) -> Result<(), crate::Error> {
+        const DELETE_FILE: &'static str = r#"
+          DELETE FROM nbmarch_files WHERE file_name = ? AND init_time = ?
+        "#;
+
+        const DELETE_CATALOG: &'static str = r#"
+          DELETE FROM nbmarch_site_catalogs WHERE init_time = ?
+        "#;
+
+        self.conn.execute(
+            DELETE_FILE,
+            &[&file_name as &dyn ToSql, &init_time.timestamp()],
+        )?;
+
+        if file_name == "locations.csv" {
+            self.conn
+                .execute(DELETE_CATALOG, &[&init_time.timestamp()])?;
+        }
+
+        Ok(())
+    }
+
+    /// The size of the database file in bytes.
+    pub(crate) fn database_size(&self) -> Result<u64, crate::Error> {
+        let page_count: i64 =
+            self.conn
+                .query_row("PRAGMA page_count", rusqlite::NO_PARAMS, |row| row.get(0))?;
+        let page_size: i64 =
+            self.conn
+                .query_row("PRAGMA page_size", rusqlite::NO_PARAMS, |row| row.get(0))?;
+
+        Ok((page_count * page_size) as u64)
+    }
+
+    /// Remove site catalogs that no initialization time uses anymore and give the free space back
+    /// to the file system.
+    pub(crate) fn compact(&self) -> Result<(), crate::Error> {
+        const DELETE_UNUSED_SITES: &'static str = r#"
+          DELETE FROM nbmarch_sites
+          WHERE catalog NOT IN (SELECT DISTINCT catalog FROM nbmarch_site_catalogs);
+          VACUUM;
+        "#;
+
+        Ok(self.conn.execute_batch(DELETE_UNUSED_SITES)?)
+    }
+}
+
+/// A file in the local store.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StoredFile {
+    /// The name of the file, e.g. "KMSO.csv" or "locations.csv".
+    pub file_name: String,
+    /// The initialization time.
+    pub init_time: chrono::NaiveDateTime,
+    /// The size of the file in bytes.
+    pub size: u64,
+}
+
+/// Times are stored as seconds since the Unix epoch, with 0 for unknown.
+pub(crate) fn from_timestamp(timestamp: i64) -> Option<chrono::NaiveDateTime> {
+    if timestamp == 0 {
+        None
+    } else {
+        Some(chrono::NaiveDateTime::from_timestamp(timestamp, 0))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    #[test]
+    fn test_unexpected_local_store_layout() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_db_file = tempfile::NamedTempFile::new()?;
+        let metadata = super::Metadata::connect(temp_db_file.path())?;
+
+        metadata
+            .conn
+            .execute_batch("CREATE TABLE files (name TEXT, time TEXT, bytes BLOB)")?;
+        assert!(metadata.backfill().is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_backfill_once() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_db_file = tempfile::NamedTempFile::new()?;
+        let metadata = super::Metadata::connect(temp_db_file.path())?;
+
+        metadata.conn.execute_batch(
+            r#"
+              CREATE TABLE files (file_name TEXT, init_time INTEGER, data BLOB);
+              INSERT INTO files VALUES ('KMSO.csv', 1614517200, x'000102');
+            "#,
+        )?;
+
+        metadata.backfill()?;
+        let files = metadata.stored_files()?;
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].size, 3);
+
+        // Text times don't match the layout, but the backfill is already done.
+        metadata
+            .conn
+            .execute_batch("INSERT INTO files VALUES ('KGPI.csv', '2021-02-28 13:00:00', x'00')")?;
+        metadata.backfill()?;
+        assert_eq!(metadata.stored_files()?.len(), 1);
+
+        let other_db_file = tempfile::NamedTempFile::new()?;
+        let other = super::Metadata::connect(other_db_file.path())?;
+        other.conn.execute_batch(
+            "CREATE TABLE files (file_name TEXT, init_time INTEGER, data BLOB);
+             INSERT INTO files VALUES ('KGPI.csv', '2021-02-28 13:00:00', x'00');",
+        )?;
+        assert!(other.backfill().is_err());
+
+        Ok(())
+    }
+}
@@ -1,5 +1,4 @@
 use std::str::FromStr;
-use chrono::{Datelike, Timelike};
 
 /// The interface to our storage for NBM 1D text files.
 ///
@@ -7,7 +6,32 @@ use chrono::{Datelike, Timelike};
 /// it will fetch it from the internet and then keep a copy in the local store for faster retrieval
 /// later.
 pub struct NBMStore {
-    local_store: filedb::FileDB,
+    pub(crate) local_store: filedb::FileDB,
+    pub(crate) metadata: crate::metadata::Metadata,
+    pub(crate) downloader: crate::download::Downloader,
+    pub(crate) access_mode: AccessMode,
+    pub(crate) max_concurrent_downloads: usize,
+    pub(crate) schedule: crate::CycleSchedule,
+    pub(crate) retention: crate::RetentionPolicy,
+}
+
+/// Controls when an [NBMStore] uses the local store and when it goes to the network.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessMode {
+    /// Use the local store if the file is there, otherwise download it and keep a copy. This is
+    /// the default.
+    CacheThenNetwork,
+    /// Never touch the network, only files already in the local store are available. Requests for
+    /// anything else fail with [Error::NotInLocalArchive](crate::Error::NotInLocalArchive).
+    CacheOnly,
+    /// Always download, replacing any copy already in the local store.
+    Refresh,
+}
+
+impl Default for AccessMode {
+    fn default() -> Self {
+        AccessMode::CacheThenNetwork
+    }
 }
 
 impl NBMStore {
@@ -28,8 +52,76 @@ impl NBMStore {
         };
 
         let local_store = filedb::FileDB::connect(&path_buf)?;
+        let metadata = crate::metadata::Metadata::connect(&path_buf)?;
+        metadata.backfill()?;
+
+        Ok(Self {
+            local_store,
+            metadata,
+            downloader: crate::download::Downloader::default(),
+            access_mode: AccessMode::default(),
+            max_concurrent_downloads: 4,
+            schedule: crate::CycleSchedule::default(),
+            retention: crate::RetentionPolicy::default(),
+        })
+    }
+
+    /// Use a different [Fetcher](crate::Fetcher) for retrieving files that are not in the local
+    /// store.
+    ///
+    /// By default a [ReqwestFetcher](crate::ReqwestFetcher) is used to download files from the
+    /// NOAA servers.
+    pub fn with_fetcher<F: crate::Fetcher + 'static>(mut self, fetcher: F) -> Self {
+        self.downloader.fetcher = std::sync::Arc::new(fetcher);
+        self
+    }
+
+    /// Set the [AccessMode] for this store.
+    pub fn with_access_mode(mut self, mode: AccessMode) -> Self {
+        self.access_mode = mode;
+        self
+    }
+
+    /// Set the [RetryPolicy](crate::RetryPolicy) used when downloading files.
+    pub fn with_retry_policy(mut self, policy: crate::RetryPolicy) -> Self {
+        self.downloader.retry = policy;
+        self
+    }
+
+    /// Set the [CycleSchedule](crate::CycleSchedule) used to map request times to NBM
+    /// initialization times.
+    pub fn with_cycle_schedule(mut self, schedule: crate::CycleSchedule) -> Self {
+        self.schedule = schedule;
+        self
+    }
+
+    /// Use a different [VersionTable](crate::VersionTable) to decide which NBM version, and so
+    /// which directory of the remote archive, goes with each initialization time.
+    pub fn with_version_table(mut self, versions: crate::VersionTable) -> Self {
+        self.downloader.versions = versions;
+        self
+    }
+
+    /// Add a new NBM version that started with the initialization time `starts`, see
+    /// [VersionTable::register()](crate::VersionTable::register).
+    pub fn register_nbm_version(
+        &mut self,
+        starts: chrono::NaiveDateTime,
+        version: crate::NBMVersion,
+    ) {
+        self.downloader.versions.register(starts, version);
+    }
+
+    /// The version of the NBM that produced the files for an initialization time.
+    pub fn nbm_version(&self, init_time: chrono::NaiveDateTime) -> &crate::NBMVersion {
+        self.downloader.versions.version_at(init_time)
+    }
 
-        Ok(Self { local_store })
+    /// Set the maximum number of files downloaded at the same time by bulk operations like
+    /// [Self::prefetch()]. The default is 4.
+    pub fn with_max_concurrent_downloads(mut self, max: usize) -> Self {
+        self.max_concurrent_downloads = max.max(1);
+        self
     }
 
     /// Validate a request.
@@ -38,34 +130,20 @@ impl NBMStore {
     /// the closest match to site. If there is no data available for the closest intitialization
     /// time, no close matches for sites, too many close matches, or some other kind of error, then
     /// a [ValidationError] is returned.
+    ///
+    /// The site can be an id ("KMSO", or "MSO" for short), a name ("missoula"), a name qualified
+    /// with a state/province ("Logan, UT" or "Missoula MT"), or explicit fields
+    /// ("id:KMSO", "name:logan pass state:mt").
     pub fn validate_request(
         &self,
         site: &str,
         request_time: chrono::NaiveDateTime,
     ) -> Result<crate::SiteValidation, crate::Error> {
-        let init_time = calculate_next_most_recent_nmb_initialization_time(request_time);
-
-        let locations_str_bytes = self.local_store.retrieve_file("locations.csv", init_time)?;
+        let init_time = self.schedule.most_recent_initialization_time(request_time);
 
-        let locations_str = if let Some(bytes) = locations_str_bytes {
-            Some(String::from_utf8(bytes)?)
-        } else {
-            match crate::download::download_file("locations.csv", init_time) {
-                Ok(str_data) => {
-                    let _err =
-                        self.local_store
-                            .add_file("locations.csv", init_time, str_data.as_bytes());
-                    Some(str_data)
-                }
-                Err(_) => None,
-            }
-        };
-
-        let locations_str =
-            locations_str.ok_or_else(|| crate::Error::InitializationTimeNotAvailable(init_time))?;
-
-        crate::site_validation::validate(site, &locations_str)
-            .map(|site_info| crate::SiteValidation::new(site_info, init_time))
+        self.locations(init_time)?.validate(site).map(|site_info| {
+            crate::SiteValidation::new(site_info, init_time, self.nbm_version(init_time).clone())
+        })
     }
 
     /// Validate a request, but keep going back in time until an available initialization time
@@ -90,7 +168,8 @@ impl NBMStore {
             let validation = self.validate_request(site, attempt_request_time);
 
             match &validation {
-                Err(crate::Error::InitializationTimeNotAvailable(init_time)) => {
+                Err(crate::Error::InitializationTimeNotAvailable(init_time))
+                | Err(crate::Error::NotInLocalArchive { init_time, .. }) => {
                     attempt_request_time = *init_time - chrono::Duration::hours(1);
                 }
                 _ => return validation,
@@ -103,39 +182,256 @@ impl NBMStore {
         }
     }
 
+    /// List the sites available for an initialization time, sorted by id.
+    ///
+    /// The list can be narrowed to a single state/province and/or to ids that start with a prefix,
+    /// both are case insensitive. The sites come from the "locations.csv" file for the most recent
+    /// initialization time at or before `init_time`.
+    pub fn list_sites(
+        &self,
+        init_time: chrono::NaiveDateTime,
+        state_prov: Option<&str>,
+        id_prefix: Option<&str>,
+    ) -> Result<Vec<crate::SiteInfo>, crate::Error> {
+        let init_time = self.schedule.most_recent_initialization_time(init_time);
+        self.locations(init_time)?.list(state_prov, id_prefix)
+    }
+
+    /// Find the sites that best match a query, best match first.
+    ///
+    /// This is the search [Self::validate_request()] falls back on when there is no exact match
+    /// for the site id, useful for offering suggestions to users.
+    pub fn search_sites(
+        &self,
+        query: &str,
+        init_time: chrono::NaiveDateTime,
+    ) -> Result<Vec<crate::SiteMatch>, crate::Error> {
+        let init_time = self.schedule.most_recent_initialization_time(init_time);
+        self.locations(init_time)?.rank_sites(query)
+    }
+
+    /// Find the `n` sites closest to a point, closest first, along with their great circle
+    /// distances in kilometers.
+    ///
+    /// The sites come from the "locations.csv" file for the most recent initialization time at or
+    /// before `init_time`.
+    pub fn nearest_sites(
+        &self,
+        latitude: f64,
+        longitude: f64,
+        init_time: chrono::NaiveDateTime,
+        n: usize,
+    ) -> Result<Vec<(crate::SiteInfo, f64)>, crate::Error> {
+        let init_time = self.schedule.most_recent_initialization_time(init_time);
+        self.locations(init_time)?.nearest(latitude, longitude, n)
+    }
+
+    /// Find all the sites within `radius` kilometers of a point, closest first, optionally only
+    /// those in one state/province.
+    ///
+    /// The sites come from the "locations.csv" file for the most recent initialization time at or
+    /// before `init_time`. Use [SiteInfo::ids()](crate::SiteInfo::ids) to pass the results to
+    /// [Self::prefetch()] or [Self::find_gaps()].
+    pub fn sites_within(
+        &self,
+        latitude: f64,
+        longitude: f64,
+        radius: f64,
+        init_time: chrono::NaiveDateTime,
+        state_prov: Option<&str>,
+    ) -> Result<Vec<crate::SiteInfo>, crate::Error> {
+        let init_time = self.schedule.most_recent_initialization_time(init_time);
+        self.locations(init_time)?
+            .within(latitude, longitude, radius, state_prov)
+    }
+
+    /// Find all the sites in a [BoundingBox](crate::BoundingBox), optionally only those in one
+    /// state/province, see [Self::sites_within()].
+    pub fn sites_in_box(
+        &self,
+        bbox: &crate::BoundingBox,
+        init_time: chrono::NaiveDateTime,
+        state_prov: Option<&str>,
+    ) -> Result<Vec<crate::SiteInfo>, crate::Error> {
+        let init_time = self.schedule.most_recent_initialization_time(init_time);
+        self.locations(init_time)?.in_box(bbox, state_prov)
+    }
+
+    /// Validate a request for the site closest to a point.
+    ///
+    /// This works like [Self::validate_request()], but selects the site by location instead of by
+    /// name.
+    pub fn validate_nearest(
+        &self,
+        latitude: f64,
+        longitude: f64,
+        request_time: chrono::NaiveDateTime,
+    ) -> Result<crate::SiteValidation, crate::Error> {
+        let init_time = self.schedule.most_recent_initialization_time(request_time);
+
+        match self
+            .locations(init_time)?
+            .nearest(latitude, longitude, 1)?
+            .pop()
+        {
+            Some((site_info, _)) => Ok(crate::SiteValidation::new(
+                site_info,
+                init_time,
+                self.nbm_version(init_time).clone(),
+            )),
+            None => Err(crate::Error::NoMatch(format!(
+                "{}, {}",
+                latitude, longitude
+            ))),
+        }
+    }
+
     /// Once a validation has been completed, it can be used to load a text file.
     pub fn retrieve(
         &self,
         validation: crate::SiteValidation,
     ) -> Result<nbm_tools::NBMData, crate::Error> {
+        self.retrieve_with_provenance(validation)
+            .map(|retrieved| retrieved.data)
+    }
+
+    /// Load a text file like [Self::retrieve()], but also report where it came from.
+    pub fn retrieve_with_provenance(
+        &self,
+        validation: crate::SiteValidation,
+    ) -> Result<crate::Retrieved, crate::Error> {
         let file_name = validation.file_name();
+        let init_time = validation.initialization_time;
 
-        let data_str = self
-            .local_store
-            .retrieve_file(&file_name, validation.initialization_time)?;
-
-        let data_str = match data_str {
-            Some(text) => Ok(String::from_utf8(text)?),
-            None => {
-                match crate::download::download_file(&file_name, validation.initialization_time) {
-                    Ok(text) => {
-                        self.local_store.add_file(
-                            &file_name,
-                            validation.initialization_time,
-                            &text.as_bytes(),
-                        )?;
-
-                        Ok(text)
-                    }
-                    err @ Err(_) => err,
-                }
+        let (data, source) = self.load_file(&file_name, init_time, |text| {
+            Ok(nbm_tools::NBMData::from_str(text)?)
+        })?;
+
+        Ok(crate::Retrieved {
+            data,
+            source,
+            provenance: self.provenance(&file_name, init_time)?,
+        })
+    }
+
+    /// Load the text of a file without parsing it, e.g. to pass the CSV along to another program.
+    pub fn retrieve_csv(&self, validation: crate::SiteValidation) -> Result<String, crate::Error> {
+        self.load_file(
+            &validation.file_name(),
+            validation.initialization_time,
+            |text| {
+                // Make sure it parses before it goes into the local store.
+                nbm_tools::NBMData::from_str(text)?;
+                Ok(text.to_owned())
+            },
+        )
+        .map(|(text, _)| text)
+    }
+
+    /// Get the [Provenance](crate::Provenance) of a file in the local store.
+    fn provenance(
+        &self,
+        file_name: &str,
+        init_time: chrono::NaiveDateTime,
+    ) -> Result<crate::Provenance, crate::Error> {
+        crate::cache::provenance(
+            &self.metadata,
+            self.nbm_version(init_time),
+            file_name,
+            init_time,
+        )
+    }
+
+    /// Load a file from the local store and/or the remote archive, depending on the
+    /// [AccessMode].
+    ///
+    /// Downloaded files are passed through `parse` before they are added to the local store, so
+    /// nothing is cached unless it can be used later.
+    pub(crate) fn load_file<T, F>(
+        &self,
+        file_name: &str,
+        init_time: chrono::NaiveDateTime,
+        parse: F,
+    ) -> Result<(T, crate::Source), crate::Error>
+    where
+        F: Fn(&str) -> Result<T, crate::Error>,
+    {
+        if let Some(text) =
+            crate::cache::cached_text(&self.local_store, self.access_mode, file_name, init_time)?
+        {
+            return Ok((parse(&text)?, crate::Source::LocalStore));
+        }
+
+        let text = self.downloader.download_file(file_name, init_time)?;
+        let value = parse(&text)?;
+        self.store_file(file_name, init_time, &text)?;
+
+        Ok((value, crate::Source::Network))
+    }
+
+    /// Get the site catalog for an initialization time, building it from the "locations.csv" file
+    /// if it isn't in the local store yet.
+    pub(crate) fn locations(
+        &self,
+        init_time: chrono::NaiveDateTime,
+    ) -> Result<crate::site_validation::Locations<'_>, crate::Error> {
+        if self.access_mode != AccessMode::Refresh {
+            if let Some(locations) = self.metadata.locations(init_time)? {
+                return Ok(locations);
             }
-        }?;
+        }
 
-        Ok(nbm_tools::NBMData::from_str(data_str.as_ref())?)
+        let locations_str = self.load_locations(init_time)?;
+        self.metadata.add_locations(init_time, &locations_str)
     }
 
-    fn default_local_store_path() -> Result<std::path::PathBuf, crate::Error> {
+    /// Load the "locations.csv" file for an initialization time.
+    pub(crate) fn load_locations(
+        &self,
+        init_time: chrono::NaiveDateTime,
+    ) -> Result<String, crate::Error> {
+        let parse = |text: &str| {
+            crate::site_validation::parse_locations(text)?;
+            Ok(text.to_owned())
+        };
+
+        match self.load_file("locations.csv", init_time, parse) {
+            Ok((text, _)) => Ok(text),
+            Err(err) if crate::download::is_not_available(&err) => {
+                Err(crate::Error::InitializationTimeNotAvailable(init_time))
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Add a downloaded file to the local store along with its provenance.
+    pub(crate) fn store_file(
+        &self,
+        file_name: &str,
+        init_time: chrono::NaiveDateTime,
+        text: &str,
+    ) -> Result<(), crate::Error> {
+        crate::cache::store_text(
+            &self.local_store,
+            &self.metadata,
+            self.nbm_version(init_time),
+            file_name,
+            init_time,
+            text,
+        )
+    }
+
+    /// Remove a file from the local store along with everything we know about it.
+    pub(crate) fn remove_file(
+        &self,
+        file_name: &str,
+        init_time: chrono::NaiveDateTime,
+    ) -> Result<(), crate::Error> {
+        self.local_store.remove_file(file_name, init_time)?;
+        self.metadata.remove_file(file_name, init_time)
+    }
+
+    pub(crate) fn default_local_store_path() -> Result<std::path::PathBuf, crate::Error> {
         dirs::data_dir()
             .map(|mut p| {
                 p.push("nbm-report");
@@ -148,25 +444,6 @@ impl NBMStore {
     }
 }
 
-fn calculate_next_most_recent_nmb_initialization_time(
-    requested_time: chrono::NaiveDateTime,
-) -> chrono::NaiveDateTime {
-    let year = requested_time.year();
-    let month = requested_time.month();
-    let day = requested_time.day();
-    let hour = requested_time.hour();
-
-    let delta: chrono::Duration = match hour {
-        hr if hour >= 19 => chrono::Duration::hours(i64::from(hr) - 19),
-        hr if hour >= 13 => chrono::Duration::hours(i64::from(hr) - 13),
-        hr if hour >= 7 => chrono::Duration::hours(i64::from(hr) - 7),
-        hr if hour >= 1 => chrono::Duration::hours(i64::from(hr) - 1),
-        _hr => chrono::Duration::hours(24 - 19),
-    };
-
-    chrono::NaiveDate::from_ymd(year, month, day).and_hms(hour, 0, 0) - delta
-}
-
 #[cfg(test)]
 mod test {
     use crate as nbmarch;
@@ -176,10 +453,23 @@ mod test {
         arch: nbmarch::NBMStore,
     }
 
+    /// Serve the files in tests/fixtures instead of downloading them. Set `NBMARCH_RECORD` to
+    /// download any that are missing and save them there.
+    fn fixtures() -> nbmarch::ReplayFetcher {
+        let fetcher =
+            nbmarch::ReplayFetcher::new(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures"));
+
+        if std::env::var_os("NBMARCH_RECORD").is_some() {
+            fetcher.with_recorder(nbmarch::ReqwestFetcher::new())
+        } else {
+            fetcher
+        }
+    }
+
     fn create_test_archive() -> Result<TestArchive, Box<dyn std::error::Error>> {
         let temp_db_file = tempfile::NamedTempFile::new()?;
         let db_fname = temp_db_file.path();
-        let arch = nbmarch::NBMStore::connect(db_fname)?;
+        let arch = nbmarch::NBMStore::connect(db_fname)?.with_fetcher(fixtures());
 
         Ok(TestArchive {
             _temp_db_file: temp_db_file,
@@ -226,6 +516,115 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn test_cache_only() -> Result<(), Box<dyn std::error::Error>> {
+        let test_archive = create_test_archive()?;
+        let init_time = chrono::NaiveDate::from_ymd(2021, 2, 28).and_hms(13, 0, 0);
+
+        let arch = test_archive
+            .arch
+            .with_access_mode(nbmarch::AccessMode::CacheOnly);
+
+        match arch.validate_request("KMSO", init_time) {
+            Err(nbmarch::Error::NotInLocalArchive {
+                file_name,
+                init_time: missing_init_time,
+            }) => {
+                assert_eq!(file_name, "locations.csv");
+                assert_eq!(missing_init_time, init_time);
+            }
+            res => panic!("Expected NotInLocalArchive, got {:?}", res),
+        }
+
+        let arch = arch.with_access_mode(nbmarch::AccessMode::CacheThenNetwork);
+        let validation = arch.validate_request("KMSO", init_time)?;
+        arch.retrieve(validation.clone())?;
+
+        let arch = arch.with_access_mode(nbmarch::AccessMode::CacheOnly);
+        let retrieved = arch.retrieve_with_provenance(validation)?;
+        assert_eq!(retrieved.source, nbmarch::Source::LocalStore);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_refresh_replaces_cached_file() -> Result<(), Box<dyn std::error::Error>> {
+        const STALE: &str = "validTime,TMP_2 m above ground\n1614520800,0\n";
+
+        let test_archive = create_test_archive()?;
+        let init_time = chrono::NaiveDate::from_ymd(2021, 2, 28).and_hms(13, 0, 0);
+
+        let arch = test_archive.arch;
+        let validation = arch.validate_request("KMSO", init_time)?;
+        arch.store_file("KMSO.csv", init_time, STALE)?;
+
+        let arch = arch.with_access_mode(nbmarch::AccessMode::Refresh);
+        let retrieved = arch.retrieve_with_provenance(validation)?;
+        assert_eq!(retrieved.source, nbmarch::Source::Network);
+
+        let fixture = std::fs::read(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/fixtures/2021/02/28/NBM4.0/13/KMSO.csv"
+        ))?;
+        assert_eq!(
+            arch.local_store.retrieve_file("KMSO.csv", init_time)?,
+            Some(fixture)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_locations_not_cached() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_db_file = tempfile::NamedTempFile::new()?;
+        let arch = nbmarch::NBMStore::connect(temp_db_file.path())?
+            .with_fetcher(|_: &str| Ok("status,message\nerror,try again later\n".to_owned()));
+
+        let init_time = chrono::NaiveDate::from_ymd(2021, 2, 28).and_hms(13, 0, 0);
+        assert!(arch.validate_request("KMSO", init_time).is_err());
+        assert!(arch
+            .local_store
+            .retrieve_file("locations.csv", init_time)?
+            .is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_validate_nearest() -> Result<(), Box<dyn std::error::Error>> {
+        let arch = &create_test_archive()?.arch;
+
+        let request_time = chrono::NaiveDate::from_ymd(2021, 2, 28).and_hms(15, 15, 0);
+
+        let validation = arch.validate_nearest(46.92, -114.09, request_time)?;
+        assert_eq!(&validation.site.id, "KMSO");
+
+        let nearest = arch.nearest_sites(46.92, -114.09, request_time, 5)?;
+        assert_eq!(nearest.len(), 5);
+        assert_eq!(&nearest[0].0.id, "KMSO");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_prefetch_sites_within() -> Result<(), Box<dyn std::error::Error>> {
+        let arch = &create_test_archive()?.arch;
+
+        let init_time = chrono::NaiveDate::from_ymd(2021, 2, 28).and_hms(13, 0, 0);
+
+        let sites = arch.sites_within(46.92, -114.09, 160.0, init_time, Some("MT"))?;
+        let ids = nbmarch::SiteInfo::ids(&sites);
+        assert_eq!(ids, vec!["KMSO", "KGPI"]);
+
+        let report = arch.prefetch(&ids, init_time..=init_time);
+        assert!(report
+            .iter()
+            .all(|item| matches!(item.outcome, Ok(nbmarch::PrefetchStatus::Downloaded))));
+        assert!(arch.find_gaps(&ids, init_time..=init_time)?.is_empty());
+
+        Ok(())
+    }
+
     #[test]
     fn test_retrieve() -> Result<(), Box<dyn std::error::Error>> {
         let arch = &create_test_archive()?.arch;
@@ -242,4 +641,74 @@ mod test {
 
         Ok(())
     }
+
+    #[test]
+    fn test_retrieve_with_provenance() -> Result<(), Box<dyn std::error::Error>> {
+        let arch = &create_test_archive()?.arch;
+
+        let request_time = chrono::NaiveDate::from_ymd(2021, 2, 28).and_hms(15, 15, 0);
+        let validation = arch.validate_request("KMSO", request_time)?;
+
+        let retrieved = arch.retrieve_with_provenance(validation.clone())?;
+        assert_eq!(retrieved.source, nbmarch::Source::Network);
+        assert_eq!(retrieved.provenance.nbm_version.name, "4.0");
+        assert!(retrieved
+            .provenance
+            .url
+            .ends_with("/2021/02/28/NBM4.0/13/KMSO.csv"));
+        assert!(retrieved.provenance.downloaded.is_some());
+
+        let cached = arch.retrieve_with_provenance(validation)?;
+        assert_eq!(cached.source, nbmarch::Source::LocalStore);
+        assert_eq!(cached.provenance, retrieved.provenance);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_forecast_history() -> Result<(), Box<dyn std::error::Error>> {
+        let arch = &create_test_archive()?.arch;
+
+        let start = chrono::NaiveDate::from_ymd(2021, 2, 27).and_hms(12, 0, 0);
+        let end = chrono::NaiveDate::from_ymd(2021, 2, 28).and_hms(8, 30, 0);
+
+        let history = arch.forecast_history("KMSO", start..=end)?;
+        assert_eq!(&history.site.id, "KMSO");
+        assert_eq!(history.runs.len(), 4);
+        assert!(history.failures.is_empty());
+
+        for run in &history.runs {
+            assert_eq!(run.valid_times.len(), 36);
+            assert_eq!(
+                run.valid_times[0],
+                run.initialization_time + chrono::Duration::hours(1)
+            );
+        }
+
+        let valid_time = chrono::NaiveDate::from_ymd(2021, 2, 28).and_hms(8, 0, 0);
+        assert_eq!(history.runs.last().unwrap().valid_times[0], valid_time);
+
+        let forecasts: Vec<_> = history
+            .at_valid_time(valid_time)
+            .into_iter()
+            .map(|(run, lead_time)| (run.initialization_time, lead_time.num_hours()))
+            .collect();
+        assert_eq!(
+            forecasts,
+            vec![
+                (
+                    chrono::NaiveDate::from_ymd(2021, 2, 27).and_hms(13, 0, 0),
+                    19
+                ),
+                (
+                    chrono::NaiveDate::from_ymd(2021, 2, 27).and_hms(19, 0, 0),
+                    13
+                ),
+                (chrono::NaiveDate::from_ymd(2021, 2, 28).and_hms(1, 0, 0), 7),
+                (chrono::NaiveDate::from_ymd(2021, 2, 28).and_hms(7, 0, 0), 1),
+            ]
+        );
+
+        Ok(())
+    }
 }
@@ -0,0 +1,313 @@
+use std::str::FromStr;
+
+/// The result of prefetching a single site for a single initialization time.
+#[derive(Debug)]
+pub struct PrefetchItem {
+    /// The site as it was requested.
+    pub site: String,
+    /// The initialization time.
+    pub initialization_time: chrono::NaiveDateTime,
+    /// Where the data came from, or why it couldn't be retrieved.
+    pub outcome: Result<PrefetchStatus, crate::Error>,
+}
+
+/// How a prefetched file ended up in the local store.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PrefetchStatus {
+    /// The file was already in the local store.
+    Cached,
+    /// The file was downloaded and added to the local store.
+    Downloaded,
+}
+
+impl crate::NBMStore {
+    /// Make sure the files for many sites and initialization times are in the local store.
+    ///
+    /// Every site is validated against the "locations.csv" file of each initialization time in the
+    /// range, and files that aren't already in the local store are downloaded in parallel, see
+    /// [Self::with_max_concurrent_downloads()]. Failures don't stop the prefetch, instead there is
+    /// one [PrefetchItem] in the report for each site and initialization time.
+    pub fn prefetch<S: AsRef<str>>(
+        &self,
+        sites: &[S],
+        init_times: std::ops::RangeInclusive<chrono::NaiveDateTime>,
+    ) -> Vec<PrefetchItem> {
+        let mut report = vec![];
+
+        for init_time in self.schedule.initialization_times(&init_times) {
+            let mut to_download: Vec<(usize, String)> = vec![];
+
+            match self.validate_all(sites, init_time) {
+                Ok(validations) => {
+                    for (site, validation) in sites.iter().zip(validations) {
+                        let outcome = validation.and_then(|validation| {
+                            let file_name = validation.file_name();
+                            match self.prefetch_from_cache(&file_name, init_time)? {
+                                Some(status) => Ok(status),
+                                None => {
+                                    to_download.push((report.len(), file_name));
+                                    Ok(PrefetchStatus::Downloaded)
+                                }
+                            }
+                        });
+
+                        report.push(PrefetchItem {
+                            site: site.as_ref().to_owned(),
+                            initialization_time: init_time,
+                            outcome,
+                        });
+                    }
+                }
+                Err(err) => {
+                    let err = std::sync::Arc::new(err);
+                    for site in sites {
+                        report.push(PrefetchItem {
+                            site: site.as_ref().to_owned(),
+                            initialization_time: init_time,
+                            outcome: Err(share_error(&err, init_time)),
+                        });
+                    }
+                }
+            }
+
+            self.download_in_parallel(to_download, init_time, &mut report);
+        }
+
+        report
+    }
+
+    /// Validate all the sites against a single locations file.
+    fn validate_all<S: AsRef<str>>(
+        &self,
+        sites: &[S],
+        init_time: chrono::NaiveDateTime,
+    ) -> Result<Vec<Result<crate::SiteValidation, crate::Error>>, crate::Error> {
+        let locations = self.locations(init_time)?;
+
+        let nbm_version = self.nbm_version(init_time);
+
+        Ok(sites
+            .iter()
+            .map(|site| {
+                locations.validate(site.as_ref()).map(|site_info| {
+                    crate::SiteValidation::new(site_info, init_time, nbm_version.clone())
+                })
+            })
+            .collect())
+    }
+
+    /// Check the local store, returns `None` if the file needs to be downloaded.
+    fn prefetch_from_cache(
+        &self,
+        file_name: &str,
+        init_time: chrono::NaiveDateTime,
+    ) -> Result<Option<PrefetchStatus>, crate::Error> {
+        if self.access_mode == crate::AccessMode::Refresh {
+            return Ok(None);
+        }
+
+        if self
+            .local_store
+            .retrieve_file(file_name, init_time)?
+            .is_some()
+        {
+            return Ok(Some(PrefetchStatus::Cached));
+        }
+
+        if self.access_mode == crate::AccessMode::CacheOnly {
+            return Err(crate::Error::NotInLocalArchive {
+                file_name: file_name.to_owned(),
+                init_time,
+            });
+        }
+
+        Ok(None)
+    }
+
+    /// Download files with a bounded pool of worker threads and store them as they arrive. The
+    /// index is the position of the file's item in the report, which gets updated on failure.
+    fn download_in_parallel(
+        &self,
+        to_download: Vec<(usize, String)>,
+        init_time: chrono::NaiveDateTime,
+        report: &mut [PrefetchItem],
+    ) {
+        if to_download.is_empty() {
+            return;
+        }
+
+        let num_workers = self.max_concurrent_downloads.min(to_download.len());
+        let queue = std::sync::Mutex::new(to_download.into_iter());
+        let downloader = &self.downloader;
+
+        std::thread::scope(|scope| {
+            let (sender, receiver) = std::sync::mpsc::channel();
+
+            for _ in 0..num_workers {
+                let sender = sender.clone();
+                let queue = &queue;
+                scope.spawn(move || loop {
+                    let next = queue.lock().ok().and_then(|mut queue| queue.next());
+                    let (idx, file_name) = match next {
+                        Some(job) => job,
+                        None => break,
+                    };
+
+                    let res = downloader.download_file(&file_name, init_time);
+                    if sender.send((idx, file_name, res)).is_err() {
+                        break;
+                    }
+                });
+            }
+            drop(sender);
+
+            for (idx, file_name, res) in receiver {
+                let stored = res.and_then(|text| {
+                    nbm_tools::NBMData::from_str(&text)?;
+                    self.store_file(&file_name, init_time, &text)
+                });
+
+                if let Err(err) = stored {
+                    report[idx].outcome = Err(err);
+                }
+            }
+        });
+    }
+}
+
+/// [crate::Error] isn't [Clone], so when loading the locations fails for many items, errors that
+/// are just an initialization time and file name are copied and the rest are shared.
+fn share_error(
+    err: &std::sync::Arc<crate::Error>,
+    init_time: chrono::NaiveDateTime,
+) -> crate::Error {
+    match err.as_ref() {
+        crate::Error::InitializationTimeNotAvailable(_) => {
+            crate::Error::InitializationTimeNotAvailable(init_time)
+        }
+        crate::Error::NotInLocalArchive {
+            file_name,
+            init_time,
+        } => crate::Error::NotInLocalArchive {
+            file_name: file_name.clone(),
+            init_time: *init_time,
+        },
+        _ => crate::Error::LocationsUnavailable {
+            init_time,
+            cause: err.clone(),
+        },
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const LOCATIONS: &str = include_str!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/fixtures/2021/02/28/NBM4.0/13/locations.csv"
+    ));
+    const KMSO: &str = include_str!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/fixtures/2021/02/28/NBM4.0/13/KMSO.csv"
+    ));
+
+    fn fake_fetcher(url: &str) -> Result<String, crate::Error> {
+        if url.ends_with("/2021/02/28/NBM4.0/13/locations.csv") {
+            Ok(LOCATIONS.to_owned())
+        } else if url.ends_with("/2021/02/28/NBM4.0/13/KMSO.csv") {
+            Ok(KMSO.to_owned())
+        } else {
+            Err(crate::Error::Http {
+                url: url.to_owned(),
+                status: 404,
+            })
+        }
+    }
+
+    #[test]
+    fn test_prefetch() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_db_file = tempfile::NamedTempFile::new()?;
+        let arch = crate::NBMStore::connect(temp_db_file.path())?.with_fetcher(fake_fetcher);
+
+        let first = chrono::NaiveDate::from_ymd(2021, 2, 28).and_hms(13, 0, 0);
+        let second = chrono::NaiveDate::from_ymd(2021, 2, 28).and_hms(19, 0, 0);
+
+        arch.store_file("KBZN.csv", first, KMSO)?;
+
+        let sites = ["KMSO", "KBZN", "KGPI", "id:KXYZ"];
+        let report = arch.prefetch(&sites, first..=second);
+        assert_eq!(report.len(), 8);
+
+        for (item, site) in report.iter().zip(sites.iter().cycle()) {
+            assert_eq!(item.site, *site);
+        }
+
+        assert!(report[..4]
+            .iter()
+            .all(|item| item.initialization_time == first));
+        assert!(matches!(report[0].outcome, Ok(PrefetchStatus::Downloaded)));
+        assert!(matches!(report[1].outcome, Ok(PrefetchStatus::Cached)));
+        assert!(matches!(
+            report[2].outcome,
+            Err(crate::Error::Http { status: 404, .. })
+        ));
+        assert!(matches!(report[3].outcome, Err(crate::Error::NoMatch(_))));
+
+        for item in &report[4..] {
+            assert_eq!(item.initialization_time, second);
+            match item.outcome {
+                Err(crate::Error::InitializationTimeNotAvailable(init_time)) => {
+                    assert_eq!(init_time, second)
+                }
+                ref outcome => panic!("Unexpected outcome {:?}", outcome),
+            }
+        }
+
+        assert_eq!(
+            arch.local_store.retrieve_file("KMSO.csv", first)?,
+            Some(KMSO.as_bytes().to_vec())
+        );
+        assert!(arch.local_store.retrieve_file("KGPI.csv", first)?.is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_prefetch_shares_locations_error() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_db_file = tempfile::NamedTempFile::new()?;
+        let arch = crate::NBMStore::connect(temp_db_file.path())?
+            .with_retry_policy(crate::RetryPolicy::no_retries())
+            .with_fetcher(|url: &str| {
+                Err(crate::Error::Http {
+                    url: url.to_owned(),
+                    status: 500,
+                })
+            });
+
+        let init_time = chrono::NaiveDate::from_ymd(2021, 2, 28).and_hms(13, 0, 0);
+        let report = arch.prefetch(&["KMSO", "KGPI"], init_time..=init_time);
+        assert_eq!(report.len(), 2);
+
+        let causes: Vec<_> = report
+            .iter()
+            .map(|item| match &item.outcome {
+                Err(crate::Error::LocationsUnavailable {
+                    init_time: it,
+                    cause,
+                }) => {
+                    assert_eq!(*it, init_time);
+                    assert!(matches!(
+                        cause.as_ref(),
+                        crate::Error::Http { status: 500, .. }
+                    ));
+                    cause.clone()
+                }
+                outcome => panic!("Unexpected outcome {:?}", outcome),
+            })
+            .collect();
+        assert!(std::sync::Arc::ptr_eq(&causes[0], &causes[1]));
+
+        Ok(())
+    }
+}
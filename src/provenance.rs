@@ -0,0 +1,61 @@
+/// Data retrieved from an [NBMStore](crate::NBMStore) along with where it came from.
+#[derive(Debug)]
+pub struct Retrieved {
+    /// The data.
+    pub data: nbm_tools::NBMData,
+    /// Whether this request was served from the local store or the network.
+    pub source: Source,
+    /// Where the file was originally downloaded from, when and which NBM version produced it.
+    pub provenance: Provenance,
+}
+
+/// Where a request for a file was served from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    /// The file was already in the local store.
+    LocalStore,
+    /// The file was downloaded for this request.
+    Network,
+}
+
+/// The history of a file in the local store.
+///
+/// This is saved alongside each file when it is added to the local store.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Provenance {
+    /// The url the file was downloaded from.
+    pub url: String,
+    /// When the file was downloaded (UTC). This is [Option::None] for files that were added to the
+    /// local store before provenance was tracked.
+    pub downloaded: Option<chrono::NaiveDateTime>,
+    /// The version of the NBM that produced the file.
+    pub nbm_version: crate::NBMVersion,
+}
+
+impl Provenance {
+    /// The provenance for a file that was just downloaded.
+    pub(crate) fn downloaded_now(
+        file_name: &str,
+        init_time: chrono::NaiveDateTime,
+        nbm_version: &crate::NBMVersion,
+    ) -> Self {
+        Self {
+            url: crate::download::build_download_url(file_name, init_time, nbm_version),
+            downloaded: Some(chrono::Utc::now().naive_utc()),
+            nbm_version: nbm_version.clone(),
+        }
+    }
+
+    /// The best guess for a file in the local store with no saved provenance.
+    pub(crate) fn unknown(
+        file_name: &str,
+        init_time: chrono::NaiveDateTime,
+        nbm_version: &crate::NBMVersion,
+    ) -> Self {
+        Self {
+            url: crate::download::build_download_url(file_name, init_time, nbm_version),
+            downloaded: None,
+            nbm_version: nbm_version.clone(),
+        }
+    }
+}
@@ -0,0 +1,164 @@
+/// Candidates scoring below this aren't worth suggesting.
+pub(crate) const MIN_SCORE: f64 = 0.6;
+
+/// The best candidate is selected automatically if it beats the next best by at least this much.
+pub(crate) const CLEAR_MARGIN: f64 = 0.1;
+
+/// Score differences this small are rounding error, not a real difference.
+const SCORE_TOLERANCE: f64 = 1.0e-9;
+
+/// Check if the best candidate beats the next best by at least [CLEAR_MARGIN].
+///
+/// The scores are floating point, so e.g. 0.9 - 0.8 comes out a hair under 0.1. A margin within
+/// [SCORE_TOLERANCE] of [CLEAR_MARGIN] still counts as clear.
+pub(crate) fn is_clear_winner(best: f64, next: f64) -> bool {
+    best - next >= CLEAR_MARGIN - SCORE_TOLERANCE
+}
+
+/// The most candidates to suggest when a site is ambiguous.
+pub(crate) const MAX_CANDIDATES: usize = 10;
+
+/// Score how well a site matches what a user typed, from 0.0 (nothing in common) to 1.0 (an exact
+/// match of the id or name).
+pub(crate) fn score(query: &str, site: &crate::SiteInfo) -> f64 {
+    let query = query.trim().to_uppercase();
+    if query.is_empty() {
+        return 0.0;
+    }
+
+    let id = site.id.to_uppercase();
+    let name = site.name.to_uppercase();
+    let state = site.state_prov.to_uppercase();
+
+    let id_score = if id == query {
+        1.0
+    } else {
+        similarity(&query, &id)
+    };
+
+    let name_score = if name == query {
+        1.0
+    } else if name.starts_with(&query) {
+        0.9
+    } else if name.contains(&query) {
+        0.8
+    } else {
+        similarity(&query, &name).max(0.95 * token_score(&query, &name))
+    };
+
+    // Matching the state is enough to suggest a site, but it ties with every other site there.
+    let state_score = if state == query { MIN_SCORE } else { 0.0 };
+
+    id_score.max(name_score).max(state_score)
+}
+
+/// How well the words in the query match the words in a name, in any order.
+fn token_score(query: &str, name: &str) -> f64 {
+    let query_tokens: Vec<&str> = tokens(query).collect();
+    let name_tokens: Vec<&str> = tokens(name).collect();
+
+    if query_tokens.is_empty() || name_tokens.is_empty() {
+        return 0.0;
+    }
+
+    let total: f64 = query_tokens
+        .iter()
+        .map(|q_tok| {
+            name_tokens
+                .iter()
+                .map(|n_tok| {
+                    if q_tok == n_tok {
+                        1.0
+                    } else if q_tok.len() >= 3 && n_tok.starts_with(q_tok) {
+                        0.9
+                    } else {
+                        similarity(q_tok, n_tok)
+                    }
+                })
+                .fold(0.0, f64::max)
+        })
+        .sum();
+
+    total / query_tokens.len() as f64
+}
+
+fn tokens(val: &str) -> impl Iterator<Item = &str> {
+    val.split(|c: char| !c.is_alphanumeric())
+        .filter(|tok| !tok.is_empty())
+}
+
+/// The edit distance scaled to the length of the longer string and flipped, so 1.0 is identical.
+fn similarity(a: &str, b: &str) -> f64 {
+    let max_len = a.chars().count().max(b.chars().count());
+    if max_len == 0 {
+        return 0.0;
+    }
+
+    1.0 - levenshtein(a, b) as f64 / max_len as f64
+}
+
+fn levenshtein(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    let mut curr = vec![0; b.len() + 1];
+
+    for (i, a_ch) in a.chars().enumerate() {
+        curr[0] = i + 1;
+        for (j, b_ch) in b.iter().enumerate() {
+            let substitution = prev[j] + if a_ch == *b_ch { 0 } else { 1 };
+            curr[j + 1] = substitution.min(prev[j + 1] + 1).min(curr[j] + 1);
+        }
+        std::mem::swap(&mut prev, &mut curr);
+    }
+
+    prev[b.len()]
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn site(id: &str, name: &str, state_prov: &str) -> crate::SiteInfo {
+        crate::SiteInfo {
+            id: id.to_owned(),
+            name: name.to_owned(),
+            state_prov: state_prov.to_owned(),
+            latitude: 0.0,
+            longitude: 0.0,
+        }
+    }
+
+    #[test]
+    fn test_is_clear_winner() {
+        // Exactly the margin, even though 0.9 - 0.8 < 0.1 in floating point.
+        assert!(is_clear_winner(0.9, 0.8));
+        assert!(is_clear_winner(1.0, 0.9));
+        assert!(is_clear_winner(1.0, 0.6));
+
+        assert!(!is_clear_winner(0.9, 0.8 + 1.0e-6));
+        assert!(!is_clear_winner(0.85, 0.8));
+        assert!(!is_clear_winner(0.8, 0.8));
+    }
+
+    #[test]
+    fn test_levenshtein() {
+        assert_eq!(levenshtein("kitten", "sitting"), 3);
+        assert_eq!(levenshtein("", "abc"), 3);
+        assert_eq!(levenshtein("MISSOULA", "MISSOULA"), 0);
+    }
+
+    #[test]
+    fn test_score() {
+        let missoula = site("KMSO", "MISSOULA", "MT");
+
+        assert_eq!(score("missoula", &missoula), 1.0);
+        assert_eq!(score("kmso", &missoula), 1.0);
+        assert!(score("misoula", &missoula) > 0.8);
+        assert_eq!(score("mt", &missoula), MIN_SCORE);
+        assert!(score("billings", &missoula) < MIN_SCORE);
+
+        let lolo = site("LOLM8", "LOLO PASS", "MT");
+        assert!(score("pass lolo", &lolo) > score("lolo", &missoula));
+    }
+}
@@ -0,0 +1,104 @@
+/// A [Fetcher](crate::Fetcher) that serves files from a directory instead of the network, e.g.
+/// for tests that need to run offline.
+///
+/// The directory mirrors the layout of the remote archive below [BASE_URL](crate::BASE_URL), so
+/// the file for `{BASE_URL}2021/02/28/NBM4.0/13/KMSO.csv` is `{dir}/2021/02/28/NBM4.0/13/KMSO.csv`.
+/// Files that aren't in the directory get an [Error::Http](crate::Error::Http) with status 404,
+/// just like files that aren't in the remote archive.
+///
+/// With a recorder, see [Self::with_recorder()], files that aren't in the directory are fetched
+/// with the recorder and saved in the directory for next time.
+pub struct ReplayFetcher {
+    dir: std::path::PathBuf,
+    recorder: Option<Box<dyn crate::Fetcher>>,
+}
+
+impl ReplayFetcher {
+    /// Serve the files in a directory.
+    pub fn new<P: Into<std::path::PathBuf>>(dir: P) -> Self {
+        Self {
+            dir: dir.into(),
+            recorder: None,
+        }
+    }
+
+    /// Fetch files that aren't in the directory yet with another fetcher, usually a
+    /// [ReqwestFetcher](crate::ReqwestFetcher), and save them in the directory.
+    pub fn with_recorder<F: crate::Fetcher + 'static>(mut self, recorder: F) -> Self {
+        self.recorder = Some(Box::new(recorder));
+        self
+    }
+
+    /// The path in the directory for a url in the remote archive.
+    fn fixture_path(&self, url: &str) -> Result<std::path::PathBuf, crate::Error> {
+        let invalid = || crate::Error::general_error(format!("Can't replay url {}", url));
+
+        let relative = url.strip_prefix(crate::BASE_URL).ok_or_else(invalid)?;
+
+        let mut path = self.dir.clone();
+        for part in relative.split('/') {
+            if part.is_empty() || part == "." || part == ".." {
+                return Err(invalid());
+            }
+            path.push(part);
+        }
+
+        Ok(path)
+    }
+}
+
+impl crate::Fetcher for ReplayFetcher {
+    fn fetch(&self, url: &str) -> Result<String, crate::Error> {
+        let path = self.fixture_path(url)?;
+
+        if path.is_file() {
+            return Ok(std::fs::read_to_string(&path)?);
+        }
+
+        match &self.recorder {
+            Some(recorder) => {
+                let text = recorder.fetch(url)?;
+                if let Some(parent) = path.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+                std::fs::write(&path, &text)?;
+                Ok(text)
+            }
+            None => Err(crate::Error::Http {
+                url: url.to_owned(),
+                status: 404,
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Fetcher;
+
+    #[test]
+    fn test_replay_and_record() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = tempfile::tempdir()?;
+        let url = format!("{}2021/02/28/NBM4.0/13/KMSO.csv", crate::BASE_URL);
+
+        let replay = ReplayFetcher::new(dir.path());
+        match replay.fetch(&url) {
+            Err(crate::Error::Http { status: 404, .. }) => {}
+            _ => panic!("Should be a 404 for a missing fixture"),
+        }
+
+        let recorder = ReplayFetcher::new(dir.path())
+            .with_recorder(|_: &str| Ok("validTime,TMP\n1614520800,35\n".to_owned()));
+        assert_eq!(recorder.fetch(&url)?, "validTime,TMP\n1614520800,35\n");
+
+        assert_eq!(replay.fetch(&url)?, "validTime,TMP\n1614520800,35\n");
+
+        assert!(replay.fetch("https://example.com/KMSO.csv").is_err());
+        assert!(replay
+            .fetch(&format!("{}2021/../../KMSO.csv", crate::BASE_URL))
+            .is_err());
+
+        Ok(())
+    }
+}
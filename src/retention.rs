@@ -0,0 +1,221 @@
+use chrono::Timelike;
+
+/// Rules for which files to remove from the local store when it is pruned, see
+/// [NBMStore::prune()](crate::NBMStore::prune).
+///
+/// Every rule is optional and the default policy keeps everything.
+#[derive(Debug, Clone, Default)]
+pub struct RetentionPolicy {
+    /// Remove files for initialization times older than this.
+    pub max_age: Option<chrono::Duration>,
+    /// Remove the files for the oldest initialization times until the files in the local store
+    /// add up to no more than this many bytes.
+    pub max_total_bytes: Option<u64>,
+    /// Only keep files for initialization times with these hours (UTC), e.g. `vec![1, 13]`.
+    pub keep_cycles: Option<Vec<u32>>,
+}
+
+/// What was removed by [NBMStore::prune()](crate::NBMStore::prune).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PruneReport {
+    /// The number of files removed.
+    pub files_removed: usize,
+    /// How many bytes smaller the database file is afterwards.
+    pub bytes_removed: u64,
+}
+
+impl crate::NBMStore {
+    /// Set the [RetentionPolicy] used by [Self::prune()].
+    pub fn with_retention_policy(mut self, policy: RetentionPolicy) -> Self {
+        self.retention = policy;
+        self
+    }
+
+    /// Remove files from the local store according to the [RetentionPolicy].
+    ///
+    /// This includes files that were in the local store before their provenance was tracked, they
+    /// are found when the store is connected. Afterwards the database is compacted so the space
+    /// goes back to the file system.
+    pub fn prune(&self) -> Result<PruneReport, crate::Error> {
+        let now = chrono::Utc::now().naive_utc();
+        let files = self.metadata.stored_files()?;
+        let size_before = self.metadata.database_size()?;
+
+        let mut report = PruneReport::default();
+        for file in select_for_removal(files, &self.retention, now) {
+            self.remove_file(&file.file_name, file.init_time)?;
+            report.files_removed += 1;
+        }
+
+        if report.files_removed > 0 {
+            self.metadata.compact()?;
+            let size_after = self.metadata.database_size()?;
+            report.bytes_removed = size_before.saturating_sub(size_after);
+        }
+
+        Ok(report)
+    }
+}
+
+/// Pick the files a policy says to remove.
+fn select_for_removal(
+    files: Vec<crate::metadata::StoredFile>,
+    policy: &RetentionPolicy,
+    now: chrono::NaiveDateTime,
+) -> Vec<crate::metadata::StoredFile> {
+    let oldest_allowed = policy.max_age.map(|max_age| now - max_age);
+
+    let (mut keep, mut remove): (Vec<_>, Vec<_>) = files.into_iter().partition(|file| {
+        let too_old = oldest_allowed
+            .map(|oldest| file.init_time < oldest)
+            .unwrap_or(false);
+
+        let other_cycle = policy
+            .keep_cycles
+            .as_ref()
+            .map(|hours| !hours.contains(&file.init_time.hour()))
+            .unwrap_or(false);
+
+        !too_old && !other_cycle
+    });
+
+    if let Some(max_total_bytes) = policy.max_total_bytes {
+        keep.sort_by(|a, b| a.init_time.cmp(&b.init_time));
+
+        let mut total: u64 = keep.iter().map(|file| file.size).sum();
+        let mut oldest = keep.into_iter();
+        while total > max_total_bytes {
+            match oldest.next() {
+                Some(file) => {
+                    total -= file.size;
+                    remove.push(file);
+                }
+                None => break,
+            }
+        }
+    }
+
+    remove
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::metadata::StoredFile;
+
+    /// Text that doesn't compress well, so removing it frees pages in the database.
+    fn noise(lines: usize) -> String {
+        let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+        let mut text = String::from("validTime,TMP_2 m above ground\n");
+        for i in 0..lines {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            text.push_str(&format!("{},{}\n", 1_614_500_000 + 3600 * i, state));
+        }
+
+        text
+    }
+
+    #[test]
+    fn test_prune_files_without_provenance() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_db_file = tempfile::NamedTempFile::new()?;
+        let old = chrono::NaiveDate::from_ymd(2021, 2, 27).and_hms(13, 0, 0);
+        let now = chrono::Utc::now().naive_utc().timestamp();
+        let new = chrono::NaiveDateTime::from_timestamp(now - now % 3600, 0);
+
+        {
+            // Seed the local store directly, like a version of this crate that didn't track files.
+            let local_store = filedb::FileDB::connect(temp_db_file.path())?;
+            local_store.add_file("KMSO.csv", old, noise(2000).as_bytes())?;
+            local_store.add_file("KMSO.csv", new, noise(10).as_bytes())?;
+        }
+
+        let arch =
+            crate::NBMStore::connect(temp_db_file.path())?.with_retention_policy(RetentionPolicy {
+                max_age: Some(chrono::Duration::days(30)),
+                ..RetentionPolicy::default()
+            });
+
+        let report = arch.prune()?;
+        assert_eq!(report.files_removed, 1);
+        assert!(report.bytes_removed > 0);
+
+        assert!(arch.local_store.retrieve_file("KMSO.csv", old)?.is_none());
+        assert!(arch.local_store.retrieve_file("KMSO.csv", new)?.is_some());
+        assert_eq!(arch.prune()?, PruneReport::default());
+
+        Ok(())
+    }
+
+    fn files() -> Vec<StoredFile> {
+        let mut files = vec![];
+        for day in 1..=3 {
+            for &hour in &[1, 7, 13, 19] {
+                for &file_name in &["locations.csv", "KMSO.csv"] {
+                    files.push(StoredFile {
+                        file_name: file_name.to_owned(),
+                        init_time: chrono::NaiveDate::from_ymd(2021, 3, day).and_hms(hour, 0, 0),
+                        size: 100,
+                    });
+                }
+            }
+        }
+
+        files
+    }
+
+    #[test]
+    fn test_default_keeps_everything() {
+        let now = chrono::NaiveDate::from_ymd(2021, 3, 4).and_hms(0, 0, 0);
+        assert!(select_for_removal(files(), &RetentionPolicy::default(), now).is_empty());
+    }
+
+    #[test]
+    fn test_max_age() {
+        let now = chrono::NaiveDate::from_ymd(2021, 3, 4).and_hms(0, 0, 0);
+        let policy = RetentionPolicy {
+            max_age: Some(chrono::Duration::days(2)),
+            ..RetentionPolicy::default()
+        };
+
+        let removed = select_for_removal(files(), &policy, now);
+        assert_eq!(removed.len(), 8);
+        assert!(removed
+            .iter()
+            .all(|file| file.init_time < chrono::NaiveDate::from_ymd(2021, 3, 2).and_hms(0, 0, 0)));
+    }
+
+    #[test]
+    fn test_keep_cycles() {
+        let now = chrono::NaiveDate::from_ymd(2021, 3, 4).and_hms(0, 0, 0);
+        let policy = RetentionPolicy {
+            keep_cycles: Some(vec![1, 13]),
+            ..RetentionPolicy::default()
+        };
+
+        let removed = select_for_removal(files(), &policy, now);
+        assert_eq!(removed.len(), 12);
+        assert!(removed
+            .iter()
+            .all(|file| file.init_time.hour() == 7 || file.init_time.hour() == 19));
+    }
+
+    #[test]
+    fn test_max_total_bytes() {
+        let now = chrono::NaiveDate::from_ymd(2021, 3, 4).and_hms(0, 0, 0);
+        let policy = RetentionPolicy {
+            max_total_bytes: Some(1000),
+            ..RetentionPolicy::default()
+        };
+
+        let removed = select_for_removal(files(), &policy, now);
+        assert_eq!(removed.len(), 14);
+
+        let newest_removed = removed.iter().map(|file| file.init_time).max().unwrap();
+        assert_eq!(
+            newest_removed,
+            chrono::NaiveDate::from_ymd(2021, 3, 2).and_hms(13, 0, 0)
+        );
+    }
+}
@@ -0,0 +1,216 @@
+use chrono::Timelike;
+
+/// The hours (UTC) of the NBM cycles available in the archive.
+///
+/// A schedule has a set of default cycle hours, and optionally other sets of cycle hours that are
+/// only valid for specific date ranges, for periods when the archive held different cycles. The
+/// default schedule is the 01, 07, 13, and 19Z cycles.
+#[derive(Debug, Clone)]
+pub struct CycleSchedule {
+    hours: Vec<u32>,
+    periods: Vec<SchedulePeriod>,
+}
+
+#[derive(Debug, Clone)]
+struct SchedulePeriod {
+    hours: Vec<u32>,
+    valid: std::ops::Range<chrono::NaiveDateTime>,
+}
+
+impl CycleSchedule {
+    /// Create a schedule with the given cycle hours.
+    ///
+    /// There must be at least one hour, and all the hours must be less than 24.
+    pub fn new(hours: &[u32]) -> Result<Self, crate::Error> {
+        Ok(Self {
+            hours: check_hours(hours)?,
+            periods: vec![],
+        })
+    }
+
+    /// A schedule with a cycle every hour.
+    pub fn hourly() -> Self {
+        Self {
+            hours: (0..24).collect(),
+            periods: vec![],
+        }
+    }
+
+    /// Use a different set of cycle hours for initialization times in the `valid` range.
+    ///
+    /// If periods overlap, the one added last takes precedence.
+    pub fn with_period(
+        mut self,
+        hours: &[u32],
+        valid: std::ops::Range<chrono::NaiveDateTime>,
+    ) -> Result<Self, crate::Error> {
+        self.periods.push(SchedulePeriod {
+            hours: check_hours(hours)?,
+            valid,
+        });
+
+        Ok(self)
+    }
+
+    /// Find the most recent initialization time at or before the requested time.
+    pub fn most_recent_initialization_time(
+        &self,
+        requested_time: chrono::NaiveDateTime,
+    ) -> chrono::NaiveDateTime {
+        let mut init_time = requested_time.date().and_hms(requested_time.hour(), 0, 0);
+
+        // Always terminates, every period has a cycle each day and they all have an end.
+        while !self.is_initialization_time(init_time) {
+            init_time = init_time - chrono::Duration::hours(1);
+        }
+
+        init_time
+    }
+
+    /// Find the first initialization time after the given time.
+    pub fn next_initialization_time(&self, time: chrono::NaiveDateTime) -> chrono::NaiveDateTime {
+        let mut init_time = time.date().and_hms(time.hour(), 0, 0) + chrono::Duration::hours(1);
+
+        while !self.is_initialization_time(init_time) {
+            init_time = init_time + chrono::Duration::hours(1);
+        }
+
+        init_time
+    }
+
+    /// All the initialization times in a range, oldest first.
+    pub fn initialization_times(
+        &self,
+        range: &std::ops::RangeInclusive<chrono::NaiveDateTime>,
+    ) -> Vec<chrono::NaiveDateTime> {
+        let mut init_times = vec![];
+
+        let mut init_time = self.most_recent_initialization_time(*range.end());
+        while init_time >= *range.start() {
+            init_times.push(init_time);
+            init_time =
+                self.most_recent_initialization_time(init_time - chrono::Duration::hours(1));
+        }
+
+        init_times.reverse();
+        init_times
+    }
+
+    /// Check if a time is the start of a cycle in this schedule.
+    pub fn is_initialization_time(&self, time: chrono::NaiveDateTime) -> bool {
+        time.minute() == 0
+            && time.second() == 0
+            && time.nanosecond() == 0
+            && self.hours_at(time).contains(&time.hour())
+    }
+
+    fn hours_at(&self, time: chrono::NaiveDateTime) -> &[u32] {
+        self.periods
+            .iter()
+            .rev()
+            .find(|period| period.valid.contains(&time))
+            .map(|period| &period.hours[..])
+            .unwrap_or(&self.hours)
+    }
+}
+
+impl Default for CycleSchedule {
+    fn default() -> Self {
+        Self {
+            hours: vec![1, 7, 13, 19],
+            periods: vec![],
+        }
+    }
+}
+
+fn check_hours(hours: &[u32]) -> Result<Vec<u32>, crate::Error> {
+    if hours.is_empty() || hours.iter().any(|&hr| hr > 23) {
+        return Err(crate::Error::general_error(format!(
+            "Invalid cycle hours: {:?}",
+            hours
+        )));
+    }
+
+    let mut hours = hours.to_vec();
+    hours.sort_unstable();
+    hours.dedup();
+
+    Ok(hours)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_default_schedule() {
+        let schedule = CycleSchedule::default();
+
+        let request_time = chrono::NaiveDate::from_ymd(2021, 2, 28).and_hms(15, 15, 0);
+        let init_time = chrono::NaiveDate::from_ymd(2021, 2, 28).and_hms(13, 0, 0);
+        assert_eq!(
+            schedule.most_recent_initialization_time(request_time),
+            init_time
+        );
+
+        let request_time = chrono::NaiveDate::from_ymd(2021, 3, 1).and_hms(0, 30, 0);
+        let init_time = chrono::NaiveDate::from_ymd(2021, 2, 28).and_hms(19, 0, 0);
+        assert_eq!(
+            schedule.most_recent_initialization_time(request_time),
+            init_time
+        );
+
+        assert_eq!(
+            schedule.next_initialization_time(init_time),
+            chrono::NaiveDate::from_ymd(2021, 3, 1).and_hms(1, 0, 0)
+        );
+        assert_eq!(
+            schedule.next_initialization_time(request_time),
+            chrono::NaiveDate::from_ymd(2021, 3, 1).and_hms(1, 0, 0)
+        );
+    }
+
+    #[test]
+    fn test_initialization_times() {
+        let schedule = CycleSchedule::default();
+
+        let start = chrono::NaiveDate::from_ymd(2021, 2, 27).and_hms(12, 0, 0);
+        let end = chrono::NaiveDate::from_ymd(2021, 2, 28).and_hms(8, 30, 0);
+
+        let expected = vec![
+            chrono::NaiveDate::from_ymd(2021, 2, 27).and_hms(13, 0, 0),
+            chrono::NaiveDate::from_ymd(2021, 2, 27).and_hms(19, 0, 0),
+            chrono::NaiveDate::from_ymd(2021, 2, 28).and_hms(1, 0, 0),
+            chrono::NaiveDate::from_ymd(2021, 2, 28).and_hms(7, 0, 0),
+        ];
+        assert_eq!(schedule.initialization_times(&(start..=end)), expected);
+    }
+
+    #[test]
+    fn test_schedule_periods() -> Result<(), crate::Error> {
+        let hourly_starts = chrono::NaiveDate::from_ymd(2021, 3, 1).and_hms(0, 0, 0);
+        let hourly_ends = chrono::NaiveDate::from_ymd(2021, 4, 1).and_hms(0, 0, 0);
+
+        let schedule = CycleSchedule::default()
+            .with_period(&(0..24).collect::<Vec<_>>(), hourly_starts..hourly_ends)?;
+
+        let request_time = chrono::NaiveDate::from_ymd(2021, 3, 2).and_hms(15, 15, 0);
+        let init_time = chrono::NaiveDate::from_ymd(2021, 3, 2).and_hms(15, 0, 0);
+        assert_eq!(
+            schedule.most_recent_initialization_time(request_time),
+            init_time
+        );
+
+        let request_time = chrono::NaiveDate::from_ymd(2021, 4, 1).and_hms(0, 30, 0);
+        let init_time = chrono::NaiveDate::from_ymd(2021, 3, 31).and_hms(23, 0, 0);
+        assert_eq!(
+            schedule.most_recent_initialization_time(request_time),
+            init_time
+        );
+
+        assert!(CycleSchedule::new(&[]).is_err());
+        assert!(CycleSchedule::new(&[24]).is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,428 @@
+use std::sync::Arc;
+
+/// A small HTTP server that makes an [NBMStore](crate::NBMStore) available to programs that
+/// aren't written in Rust. This type is only available with the `serve` feature.
+///
+/// All endpoints take the `GET` method and an optional `time` parameter, e.g.
+/// `time=2021-02-28T13:00`, which defaults to now:
+///  - `/sites?q=QUERY` ranks the sites that match a query, see
+///    [NBMStore::search_sites()](crate::NBMStore::search_sites),
+///  - `/validate?site=SITE` validates a request, see
+///    [NBMStore::validate_request()](crate::NBMStore::validate_request),
+///  - `/csv?site=SITE` returns the CSV file,
+///  - `/json?site=SITE` returns the CSV file converted to JSON, with an array of values for each
+///    column.
+///
+/// Errors are returned as JSON with a message, and a status of 404 for
+/// [Error::NoMatch](crate::Error::NoMatch) and for initialization times that aren't available,
+/// or 409 (conflict) with the candidate sites for
+/// [Error::AmbiguousSite](crate::Error::AmbiguousSite).
+pub struct Server {
+    connect: Box<dyn Fn() -> Result<crate::NBMStore, crate::Error> + Send + Sync>,
+    threads: usize,
+}
+
+impl Server {
+    /// Create a server that uses `connect` to open a store for each thread, e.g.
+    /// `|| NBMStore::connect(path)`. All the stores should share the same database file.
+    pub fn new<F>(connect: F) -> Self
+    where
+        F: Fn() -> Result<crate::NBMStore, crate::Error> + Send + Sync + 'static,
+    {
+        Self {
+            connect: Box::new(connect),
+            threads: 4,
+        }
+    }
+
+    /// Set the number of threads handling requests. The default is 4.
+    ///
+    /// Each thread has its own connection to the store, so a slow download only holds up the
+    /// request that needs it.
+    pub fn with_threads(mut self, threads: usize) -> Self {
+        self.threads = threads.max(1);
+        self
+    }
+
+    /// Listen on an address, e.g. "127.0.0.1:8080", and handle requests until the process ends.
+    pub fn run(&self, addr: &str) -> Result<(), crate::Error> {
+        let stores = (0..self.threads)
+            .map(|_| (self.connect)())
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let server = Arc::new(tiny_http::Server::http(addr).map_err(crate::Error::Internal)?);
+
+        let workers: Vec<_> = stores
+            .into_iter()
+            .map(|store| {
+                let server = server.clone();
+
+                std::thread::spawn(move || {
+                    for request in server.incoming_requests() {
+                        let response = if *request.method() == tiny_http::Method::Get {
+                            handle(&store, request.url())
+                        } else {
+                            Response::error(405, "Only GET requests are supported")
+                        };
+
+                        // The only failures are clients that hung up, and they don't need an answer.
+                        let _ = request.respond(response.into_tiny_http());
+                    }
+                })
+            })
+            .collect();
+
+        for worker in workers {
+            worker
+                .join()
+                .map_err(|_| crate::Error::general_error("A server thread panicked".to_owned()))?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct Response {
+    status: u16,
+    content_type: &'static str,
+    body: String,
+}
+
+impl Response {
+    fn json(body: String) -> Self {
+        Self {
+            status: 200,
+            content_type: "application/json",
+            body,
+        }
+    }
+
+    fn csv(body: String) -> Self {
+        Self {
+            status: 200,
+            content_type: "text/csv",
+            body,
+        }
+    }
+
+    fn error(status: u16, msg: &str) -> Self {
+        Self {
+            status,
+            content_type: "application/json",
+            body: format!("{{\"error\":{}}}", json_string(msg)),
+        }
+    }
+
+    fn from_error(err: &crate::Error) -> Self {
+        use crate::Error::*;
+
+        match err {
+            NoMatch(_) | InitializationTimeNotAvailable(_) | NotInLocalArchive { .. } => {
+                Self::error(404, &err.to_string())
+            }
+            AmbiguousSite { matches } => Self {
+                status: 409,
+                content_type: "application/json",
+                body: format!(
+                    "{{\"error\":{},\"matches\":{}}}",
+                    json_string("Ambiguous site"),
+                    site_matches_json(matches)
+                ),
+            },
+            LocationsUnavailable { cause, .. } => Self::from_error(cause),
+            Http { .. } | RetriesExhausted { .. } | InvalidContent { .. } => {
+                Self::error(502, &err.to_string())
+            }
+            _ => Self::error(500, &err.to_string()),
+        }
+    }
+
+    fn into_tiny_http(self) -> tiny_http::Response<std::io::Cursor<Vec<u8>>> {
+        let response = tiny_http::Response::from_string(self.body).with_status_code(self.status);
+
+        match tiny_http::Header::from_bytes(&b"Content-Type"[..], self.content_type.as_bytes()) {
+            Ok(header) => response.with_header(header),
+            Err(()) => response,
+        }
+    }
+}
+
+/// Route a request to the store.
+fn handle(store: &crate::NBMStore, url: &str) -> Response {
+    let (path, query) = match url.find('?') {
+        Some(idx) => (&url[..idx], &url[idx + 1..]),
+        None => (url, ""),
+    };
+    let params = parse_query(query);
+    let param = |name: &str| {
+        params
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, val)| val.as_str())
+    };
+
+    let request_time = match param("time").map(parse_time) {
+        Some(Some(time)) => time,
+        Some(None) => return Response::error(400, "Invalid time, use e.g. 2021-02-28T13:00"),
+        None => chrono::Utc::now().naive_utc(),
+    };
+
+    let result = match (path, param("q"), param("site")) {
+        ("/sites", Some(query), _) => store
+            .search_sites(query, request_time)
+            .map(|matches| Response::json(site_matches_json(&matches))),
+        ("/validate", _, Some(site)) => store
+            .validate_request(site, request_time)
+            .map(|validation| Response::json(validation_json(&validation))),
+        ("/csv", _, Some(site)) => store
+            .validate_request(site, request_time)
+            .and_then(|validation| store.retrieve_csv(validation))
+            .map(Response::csv),
+        ("/json", _, Some(site)) => {
+            store
+                .validate_request(site, request_time)
+                .and_then(|validation| {
+                    let json = validation_json(&validation);
+                    let csv = store.retrieve_csv(validation)?;
+                    Ok(Response::json(format!(
+                        "{{\"validation\":{},\"data\":{}}}",
+                        json,
+                        csv_to_json(&csv)?
+                    )))
+                })
+        }
+        ("/sites", None, _) => return Response::error(400, "Missing parameter q"),
+        ("/validate", _, None) | ("/csv", _, None) | ("/json", _, None) => {
+            return Response::error(400, "Missing parameter site")
+        }
+        _ => return Response::error(404, "Unknown endpoint"),
+    };
+
+    result.unwrap_or_else(|err| Response::from_error(&err))
+}
+
+/// Split a query string into decoded key/value pairs.
+fn parse_query(query: &str) -> Vec<(String, String)> {
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let mut parts = pair.splitn(2, '=');
+            let key = percent_decode(parts.next().unwrap_or(""));
+            let val = percent_decode(parts.next().unwrap_or(""));
+            (key, val)
+        })
+        .collect()
+}
+
+fn percent_decode(val: &str) -> String {
+    let bytes = val.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => decoded.push(b' '),
+            b'%' => match val
+                .get(i + 1..i + 3)
+                .filter(|hex| hex.chars().all(|c| c.is_ascii_hexdigit()))
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            {
+                Some(byte) => {
+                    decoded.push(byte);
+                    i += 2;
+                }
+                None => decoded.push(b'%'),
+            },
+            byte => decoded.push(byte),
+        }
+        i += 1;
+    }
+
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn parse_time(val: &str) -> Option<chrono::NaiveDateTime> {
+    const FORMATS: &[&str] = &["%Y-%m-%dT%H:%M:%S", "%Y-%m-%dT%H:%M", "%Y-%m-%d %H:%M"];
+
+    FORMATS
+        .iter()
+        .find_map(|fmt| chrono::NaiveDateTime::parse_from_str(val, fmt).ok())
+}
+
+/// Convert a CSV file to a JSON object with an array of values for each column. Numbers are
+/// converted to JSON numbers, empty values to null, and everything else is left as a string.
+fn csv_to_json(text: &str) -> Result<String, crate::Error> {
+    let mut rdr = csv::Reader::from_reader(text.as_bytes());
+
+    let headers: Vec<String> = rdr.headers()?.iter().map(str::to_owned).collect();
+    let mut columns: Vec<Vec<String>> = vec![vec![]; headers.len()];
+
+    for rec in rdr.records() {
+        let rec = rec?;
+        for (column, val) in columns.iter_mut().zip(rec.iter()) {
+            let val = val.trim();
+            column.push(if val.is_empty() {
+                "null".to_owned()
+            } else {
+                match val.parse::<f64>() {
+                    Ok(num) if num.is_finite() => num.to_string(),
+                    _ => json_string(val),
+                }
+            });
+        }
+    }
+
+    let fields: Vec<String> = headers
+        .iter()
+        .zip(columns)
+        .map(|(header, column)| format!("{}:[{}]", json_string(header), column.join(",")))
+        .collect();
+
+    Ok(format!("{{{}}}", fields.join(",")))
+}
+
+fn validation_json(validation: &crate::SiteValidation) -> String {
+    format!(
+        "{{\"site\":{},\"initialization_time\":{},\"nbm_version\":{}}}",
+        site_json(&validation.site),
+        json_string(
+            &validation
+                .initialization_time
+                .format("%Y-%m-%dT%H:%M:%SZ")
+                .to_string()
+        ),
+        json_string(&validation.nbm_version.name)
+    )
+}
+
+fn site_matches_json(matches: &[crate::SiteMatch]) -> String {
+    let matches: Vec<String> = matches
+        .iter()
+        .map(|site_match| {
+            format!(
+                "{{\"site\":{},\"score\":{}}}",
+                site_json(&site_match.site),
+                site_match.score
+            )
+        })
+        .collect();
+
+    format!("[{}]", matches.join(","))
+}
+
+fn site_json(site: &crate::SiteInfo) -> String {
+    format!(
+        "{{\"id\":{},\"name\":{},\"state_prov\":{},\"latitude\":{},\"longitude\":{}}}",
+        json_string(&site.id),
+        json_string(&site.name),
+        json_string(&site.state_prov),
+        site.latitude,
+        site.longitude
+    )
+}
+
+fn json_string(val: &str) -> String {
+    let mut json = String::with_capacity(val.len() + 2);
+    json.push('"');
+    for c in val.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if (c as u32) < 0x20 => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const LOCATIONS: &str = "id,name,state,lat,lon\n\
+                             KMSO,MISSOULA,MT,46.92,-114.09\n\
+                             KLGU,LOGAN,UT,41.79,-111.85\n\
+                             K6L4,LOGAN,WV,37.86,-81.99\n\
+                             LGNM8,LOGAN PASS,MT,48.70,-113.72\n";
+
+    fn create_test_store() -> Result<(tempfile::NamedTempFile, crate::NBMStore), crate::Error> {
+        let temp_db_file = tempfile::NamedTempFile::new()?;
+        let store = crate::NBMStore::connect(temp_db_file.path())?
+            .with_access_mode(crate::AccessMode::CacheOnly);
+
+        let init_time = chrono::NaiveDate::from_ymd(2021, 2, 28).and_hms(13, 0, 0);
+        store.store_file("locations.csv", init_time, LOCATIONS)?;
+
+        Ok((temp_db_file, store))
+    }
+
+    #[test]
+    fn test_handle() -> Result<(), crate::Error> {
+        let (_temp_db_file, store) = create_test_store()?;
+
+        let response = handle(&store, "/validate?site=missoula&time=2021-02-28T15:15");
+        assert_eq!(response.status, 200);
+        assert!(response.body.contains("\"id\":\"KMSO\""));
+        assert!(response
+            .body
+            .contains("\"initialization_time\":\"2021-02-28T13:00:00Z\""));
+
+        let response = handle(&store, "/validate?site=Logan%2C+UT&time=2021-02-28T15:15");
+        assert_eq!(response.status, 200);
+        assert!(response.body.contains("\"id\":\"KLGU\""));
+
+        let response = handle(&store, "/validate?site=logan&time=2021-02-28T15:15");
+        assert_eq!(response.status, 409);
+        assert!(response.body.contains("\"matches\":["));
+
+        let response = handle(&store, "/validate?site=id:KXYZ&time=2021-02-28T15:15");
+        assert_eq!(response.status, 404);
+
+        let response = handle(&store, "/validate?site=KMSO&time=2021-02-27T13:00");
+        assert_eq!(response.status, 404);
+
+        let response = handle(&store, "/sites?q=logan&time=2021-02-28T15:15");
+        assert_eq!(response.status, 200);
+        assert!(response.body.starts_with("[{\"site\":"));
+
+        assert_eq!(handle(&store, "/validate").status, 400);
+        assert_eq!(handle(&store, "/validate?site=KMSO&time=noon").status, 400);
+        assert_eq!(handle(&store, "/forecast?site=KMSO").status, 404);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_csv_to_json() -> Result<(), crate::Error> {
+        let csv =
+            "validTime,TMP,WX\n2021-02-28 14:00,35,\n2021-02-28 15:00,36.5,\"RA \"\"light\"\"\"\n";
+
+        assert_eq!(
+            csv_to_json(csv)?,
+            "{\"validTime\":[\"2021-02-28 14:00\",\"2021-02-28 15:00\"],\
+             \"TMP\":[35,36.5],\
+             \"WX\":[null,\"RA \\\"light\\\"\"]}"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_percent_decode() {
+        assert_eq!(percent_decode("Logan%2C+UT"), "Logan, UT");
+        assert_eq!(percent_decode("100%"), "100%");
+        assert_eq!(
+            parse_query("site=id%3AKMSO&time="),
+            vec![
+                ("site".to_owned(), "id:KMSO".to_owned()),
+                ("time".to_owned(), "".to_owned())
+            ]
+        );
+    }
+}
@@ -0,0 +1,198 @@
+/// A request for a site as typed by a user, broken into the parts we know how to search for.
+///
+/// Supported forms:
+///  - plain text, e.g. "missoula" or "KMSO", is checked against ids, then names,
+///  - a name with a state/province, e.g. "Logan, UT" or "Missoula MT",
+///  - explicit fields, e.g. "id:KMSO" or "name:logan pass state:mt",
+///  - 3 letter identifiers, e.g. "MSO", which also match the ICAO id with a K prefix.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct SiteQuery {
+    /// An identifier to look up exactly.
+    pub(crate) id: Option<String>,
+    /// A name, or part of one, to rank sites against.
+    pub(crate) name: Option<String>,
+    /// Only consider sites in this state/province.
+    pub(crate) state: Option<String>,
+    /// The state was guessed from a trailing word, e.g. the "MT" in "Missoula MT", so if there are
+    /// no matches it's worth trying again without it.
+    pub(crate) implied_state: bool,
+}
+
+const FIELDS: &[&str] = &["id:", "name:", "state:"];
+
+impl SiteQuery {
+    pub(crate) fn parse(input: &str) -> Self {
+        let input = input.trim();
+
+        let has_fields = input
+            .split_whitespace()
+            .any(|tok| field_prefix(tok).is_some());
+
+        if has_fields {
+            Self::parse_fields(input)
+        } else if let Some(comma) = input.rfind(',') {
+            let (name, state) = (input[..comma].trim(), input[comma + 1..].trim());
+            if is_state(state) && !name.is_empty() {
+                Self {
+                    name: Some(name.to_owned()),
+                    state: Some(state.to_owned()),
+                    ..Self::default()
+                }
+            } else {
+                Self::plain(input)
+            }
+        } else {
+            match input.rsplitn(2, char::is_whitespace).collect::<Vec<_>>()[..] {
+                [state, name] if state.len() == 2 && is_state(state) => Self {
+                    name: Some(name.trim().to_owned()),
+                    state: Some(state.to_owned()),
+                    implied_state: true,
+                    ..Self::default()
+                },
+                _ => Self::plain(input),
+            }
+        }
+    }
+
+    /// The ids to try for an exact match, in order.
+    pub(crate) fn id_candidates(&self) -> Vec<String> {
+        let id = match (&self.id, &self.name, &self.state) {
+            (Some(id), _, _) => id,
+            (None, Some(name), None) => name,
+            _ => return vec![],
+        };
+
+        let mut candidates = vec![id.clone()];
+        if id.len() == 3 && id.chars().all(|c| c.is_ascii_alphabetic()) {
+            candidates.push(format!("K{}", id));
+        }
+
+        candidates
+    }
+
+    /// The same query without an implied state.
+    pub(crate) fn without_implied_state(&self) -> Option<Self> {
+        if !self.implied_state {
+            return None;
+        }
+
+        let name = match (&self.name, &self.state) {
+            (Some(name), Some(state)) => format!("{} {}", name, state),
+            _ => return None,
+        };
+
+        Some(Self::plain(&name))
+    }
+
+    fn plain(input: &str) -> Self {
+        Self {
+            name: Some(input.to_owned()),
+            ..Self::default()
+        }
+    }
+
+    fn parse_fields(input: &str) -> Self {
+        let mut query = Self::default();
+
+        let mut field = "name:";
+        let mut values: Vec<(&str, Vec<&str>)> = vec![];
+
+        for tok in input.split_whitespace() {
+            let tok = match field_prefix(tok) {
+                Some(prefix) => {
+                    field = prefix;
+                    &tok[prefix.len()..]
+                }
+                None => tok,
+            };
+
+            let tok = tok.trim_matches(',');
+            if tok.is_empty() {
+                continue;
+            }
+
+            match values.iter_mut().find(|(fld, _)| *fld == field) {
+                Some((_, words)) => words.push(tok),
+                None => values.push((field, vec![tok])),
+            }
+        }
+
+        for (field, words) in values {
+            let value = Some(words.join(" "));
+            match field {
+                "id:" => query.id = value,
+                "state:" => query.state = value,
+                _ => query.name = value,
+            }
+        }
+
+        query
+    }
+}
+
+fn field_prefix(tok: &str) -> Option<&'static str> {
+    FIELDS.iter().cloned().find(|prefix| {
+        tok.get(..prefix.len())
+            .map(|head| head.eq_ignore_ascii_case(prefix))
+            .unwrap_or(false)
+    })
+}
+
+fn is_state(val: &str) -> bool {
+    (2..=3).contains(&val.len()) && val.chars().all(|c| c.is_ascii_alphabetic())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn query(id: Option<&str>, name: Option<&str>, state: Option<&str>) -> SiteQuery {
+        SiteQuery {
+            id: id.map(str::to_owned),
+            name: name.map(str::to_owned),
+            state: state.map(str::to_owned),
+            implied_state: false,
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            SiteQuery::parse("missoula"),
+            query(None, Some("missoula"), None)
+        );
+        assert_eq!(
+            SiteQuery::parse("Logan, UT"),
+            query(None, Some("Logan"), Some("UT"))
+        );
+        assert_eq!(
+            SiteQuery::parse("Logan Pass, Glacier NP"),
+            query(None, Some("Logan Pass, Glacier NP"), None)
+        );
+        assert_eq!(SiteQuery::parse("id:KMSO"), query(Some("KMSO"), None, None));
+        assert_eq!(
+            SiteQuery::parse("name:logan pass state:mt"),
+            query(None, Some("logan pass"), Some("mt"))
+        );
+        assert_eq!(
+            SiteQuery::parse("ID: kmso"),
+            query(Some("kmso"), None, None)
+        );
+
+        let implied = SiteQuery::parse("Missoula MT");
+        assert_eq!(implied.name.as_deref(), Some("Missoula"));
+        assert_eq!(implied.state.as_deref(), Some("MT"));
+        assert!(implied.implied_state);
+        assert_eq!(
+            implied.without_implied_state(),
+            Some(query(None, Some("Missoula MT"), None))
+        );
+    }
+
+    #[test]
+    fn test_id_candidates() {
+        assert_eq!(SiteQuery::parse("MSO").id_candidates(), vec!["MSO", "KMSO"]);
+        assert_eq!(SiteQuery::parse("id:kmso").id_candidates(), vec!["kmso"]);
+        assert!(SiteQuery::parse("Logan, UT").id_candidates().is_empty());
+    }
+}
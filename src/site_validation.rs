@@ -1,3 +1,4 @@
+use crate::site_query::SiteQuery;
 use rusqlite::{OptionalExtension, ToSql};
 use std::fmt::Display;
 
@@ -14,14 +15,21 @@ pub struct SiteValidation {
     /// The initialization time for which data is available. This may be different than the time
     /// for which data was requested.
     pub initialization_time: chrono::NaiveDateTime,
+    /// The version of the NBM that produced the data for this initialization time.
+    pub nbm_version: crate::NBMVersion,
 }
 
 impl SiteValidation {
     /// Create a new SiteValidation object.
-    pub(crate) fn new(site: SiteInfo, initialization_time: chrono::NaiveDateTime) -> Self {
+    pub(crate) fn new(
+        site: SiteInfo,
+        initialization_time: chrono::NaiveDateTime,
+        nbm_version: crate::NBMVersion,
+    ) -> Self {
         Self {
             site,
             initialization_time,
+            nbm_version,
         }
     }
 
@@ -31,147 +39,385 @@ impl SiteValidation {
     }
 }
 
-/// Validate a site against a "locations.csv" file.
-pub(crate) fn validate(site: &str, locations_str: &str) -> Result<SiteInfo, crate::Error> {
-    let loc_db = rusqlite::Connection::open_in_memory()?;
-    build_locations_database(&loc_db, locations_str)?;
+/// A searchable "locations.csv" file for one initialization time, kept in the local store.
+///
+/// Catalogs are saved the first time the "locations.csv" file for an initialization time is
+/// loaded, and identical files from different initialization times share a single catalog.
+pub(crate) struct Locations<'a> {
+    conn: &'a rusqlite::Connection,
+    catalog: i64,
+}
+
+impl<'a> Locations<'a> {
+    /// Validate a site against these locations.
+    ///
+    /// The site is parsed as a [SiteQuery](crate::site_query::SiteQuery), so it may be qualified
+    /// with a state/province or explicit fields like "id:KMSO".
+    pub(crate) fn validate(&self, site: &str) -> Result<SiteInfo, crate::Error> {
+        let query = SiteQuery::parse(site);
+
+        // Aliases are chosen by the user, so they win over guessing at ids, e.g. "MSO" -> "KMSO".
+        if query.id.is_none() {
+            if let Some(site_id) = crate::aliases::resolve_alias(self.conn, site)? {
+                return self
+                    .find_exact_case_insensitive_match(&site_id)?
+                    .ok_or(crate::Error::NoMatch(site_id));
+            }
+        }
+
+        for id in query.id_candidates() {
+            if let Some(site_info) = self.find_exact_case_insensitive_match(&id)? {
+                return Ok(site_info);
+            }
+        }
+
+        if query.id.is_some() {
+            return Err(crate::Error::NoMatch(site.to_owned()));
+        }
+
+        let mut matches = self.rank_query(&query)?;
+
+        let clear_winner = match &matches[..] {
+            [] => return Err(crate::Error::NoMatch(site.to_owned())),
+            [_] => true,
+            [best, next, ..] => crate::ranking::is_clear_winner(best.score, next.score),
+        };
+
+        if clear_winner {
+            Ok(matches.swap_remove(0).site)
+        } else {
+            Err(crate::Error::AmbiguousSite { matches })
+        }
+    }
+
+    /// The sites that could be what the user meant, best match first.
+    pub(crate) fn rank_sites(&self, site: &str) -> Result<Vec<SiteMatch>, crate::Error> {
+        self.rank_query(&SiteQuery::parse(site))
+    }
+
+    fn rank_query(&self, query: &SiteQuery) -> Result<Vec<SiteMatch>, crate::Error> {
+        let mut matches: Vec<SiteMatch> = self
+            .sites_in_state(query.state.as_deref())?
+            .into_iter()
+            .map(|site_info| SiteMatch {
+                score: match &query.name {
+                    Some(name) => crate::ranking::score(name, &site_info),
+                    None => crate::ranking::MIN_SCORE,
+                },
+                site: site_info,
+            })
+            .filter(|site_match| site_match.score >= crate::ranking::MIN_SCORE)
+            .collect();
+
+        if matches.is_empty() {
+            if let Some(query) = query.without_implied_state() {
+                return self.rank_query(&query);
+            }
+        }
+
+        matches.sort_by(|a, b| {
+            b.score
+                .partial_cmp(&a.score)
+                .unwrap_or(std::cmp::Ordering::Equal)
+                .then_with(|| a.site.id.cmp(&b.site.id))
+        });
+        matches.truncate(crate::ranking::MAX_CANDIDATES);
+
+        Ok(matches)
+    }
+
+    /// All the sites in the catalog.
+    pub(crate) fn sites(&self) -> Result<Vec<SiteInfo>, crate::Error> {
+        self.sites_in_state(None)
+    }
+
+    /// All the sites in the catalog in a state/province, or all of them if `state_prov` is
+    /// [Option::None].
+    pub(crate) fn sites_in_state(
+        &self,
+        state_prov: Option<&str>,
+    ) -> Result<Vec<SiteInfo>, crate::Error> {
+        const QUERY_SITES: &'static str = r#"
+            SELECT id, name, state, lat, lon FROM nbmarch_sites
+            WHERE catalog = ?1 AND (?2 IS NULL OR state = ?2 COLLATE NOCASE)
+            ORDER BY id
+        "#;
+
+        let mut stmt = self.conn.prepare(QUERY_SITES)?;
+        let temp = stmt.query_map(
+            &[&self.catalog as &dyn ToSql, &state_prov],
+            site_info_from_row,
+        )?;
+
+        Ok(temp.filter_map(|res| res.ok()).collect())
+    }
+
+    /// All the sites in a state/province and/or with ids starting with a prefix, sorted by id.
+    pub(crate) fn list(
+        &self,
+        state_prov: Option<&str>,
+        id_prefix: Option<&str>,
+    ) -> Result<Vec<SiteInfo>, crate::Error> {
+        let id_prefix = id_prefix.map(str::to_uppercase);
+
+        Ok(self
+            .sites_in_state(state_prov)?
+            .into_iter()
+            .filter(|site| match &id_prefix {
+                Some(prefix) => site.id.to_uppercase().starts_with(prefix),
+                None => true,
+            })
+            .collect())
+    }
+
+    /// All the sites within `radius` kilometers of a point, closest first.
+    pub(crate) fn within(
+        &self,
+        latitude: f64,
+        longitude: f64,
+        radius: f64,
+        state_prov: Option<&str>,
+    ) -> Result<Vec<SiteInfo>, crate::Error> {
+        let mut sites: Vec<(SiteInfo, f64)> = self
+            .sites_in_state(state_prov)?
+            .into_iter()
+            .map(|site| {
+                let distance = site.distance_to(latitude, longitude);
+                (site, distance)
+            })
+            .filter(|(_, distance)| *distance <= radius)
+            .collect();
+
+        sites.sort_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+
+        Ok(sites.into_iter().map(|(site, _)| site).collect())
+    }
+
+    /// All the sites inside a [BoundingBox], sorted by id.
+    pub(crate) fn in_box(
+        &self,
+        bbox: &BoundingBox,
+        state_prov: Option<&str>,
+    ) -> Result<Vec<SiteInfo>, crate::Error> {
+        Ok(self
+            .sites_in_state(state_prov)?
+            .into_iter()
+            .filter(|site| bbox.contains(f64::from(site.latitude), f64::from(site.longitude)))
+            .collect())
+    }
+
+    /// The `n` sites closest to a point and their distances in kilometers, closest first.
+    pub(crate) fn nearest(
+        &self,
+        latitude: f64,
+        longitude: f64,
+        n: usize,
+    ) -> Result<Vec<(SiteInfo, f64)>, crate::Error> {
+        let mut sites: Vec<(SiteInfo, f64)> = self
+            .sites()?
+            .into_iter()
+            .map(|site| {
+                let distance = site.distance_to(latitude, longitude);
+                (site, distance)
+            })
+            .collect();
+
+        sites.sort_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+        sites.truncate(n);
+
+        Ok(sites)
+    }
+
+    fn find_exact_case_insensitive_match(
+        &self,
+        site: &str,
+    ) -> Result<Option<SiteInfo>, crate::Error> {
+        const QUERY_EXACT_MATCH: &'static str = r#"
+            SELECT id, name, state, lat, lon FROM nbmarch_sites
+            WHERE catalog = ? AND id = ? COLLATE NOCASE
+        "#;
+
+        Ok(self
+            .conn
+            .query_row(
+                QUERY_EXACT_MATCH,
+                &[&self.catalog as &dyn ToSql, &site],
+                site_info_from_row,
+            )
+            .optional()?)
+    }
+}
 
-    match find_exact_case_insensitive_match(&loc_db, site) {
-        Ok(Some(site_info)) => return Ok(site_info),
-        Err(err) => return Err(err),
-        Ok(None) => {}
+impl crate::metadata::Metadata {
+    /// Get the site catalog for an initialization time, if it has been saved.
+    pub(crate) fn locations(
+        &self,
+        init_time: chrono::NaiveDateTime,
+    ) -> Result<Option<Locations<'_>>, crate::Error> {
+        const QUERY_CATALOG: &'static str = r#"
+            SELECT catalog FROM nbmarch_site_catalogs WHERE init_time = ?
+        "#;
+
+        let catalog: Option<i64> = self
+            .conn
+            .query_row(QUERY_CATALOG, &[&init_time.timestamp()], |row| row.get(0))
+            .optional()?;
+
+        Ok(catalog.map(|catalog| Locations {
+            conn: &self.conn,
+            catalog,
+        }))
     }
 
-    let matches = find_similar_sites(&loc_db, site)?;
+    /// Save the site catalog from a "locations.csv" file for an initialization time.
+    pub(crate) fn add_locations(
+        &self,
+        init_time: chrono::NaiveDateTime,
+        locations_str: &str,
+    ) -> Result<Locations<'_>, crate::Error> {
+        const INSERT_CATALOG: &'static str = r#"
+            INSERT OR REPLACE INTO nbmarch_site_catalogs (init_time, catalog) VALUES (?, ?)
+        "#;
+
+        let catalog = catalog_id(locations_str);
 
-    dbg!(matches.len());
+        self.conn.execute_batch("BEGIN TRANSACTION")?;
+        let res = build_locations_database(&self.conn, catalog, locations_str).and_then(|_| {
+            self.conn.execute(
+                INSERT_CATALOG,
+                &[&init_time.timestamp() as &dyn ToSql, &catalog],
+            )?;
+            Ok(())
+        });
+
+        match res {
+            Ok(()) => self.conn.execute_batch("COMMIT")?,
+            Err(err) => {
+                self.conn.execute_batch("ROLLBACK")?;
+                return Err(err);
+            }
+        }
 
-    if matches.is_empty() {
-        Err(crate::Error::NoMatch(site.to_owned()))
-    } else if matches.len() > 1 {
-        Err(crate::Error::AmbiguousSite { matches })
-    } else {
-        Ok(matches.into_iter().next().unwrap())
+        Ok(Locations {
+            conn: &self.conn,
+            catalog,
+        })
     }
 }
 
+/// Identical "locations.csv" files always map to the same catalog.
+///
+/// The id is saved in the database, so this uses the 64 bit FNV-1a hash, which unlike the
+/// standard library hashers is guaranteed not to change between Rust releases.
+fn catalog_id(locations_str: &str) -> i64 {
+    const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+    let hash = locations_str.bytes().fold(FNV_OFFSET_BASIS, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(FNV_PRIME)
+    });
+
+    hash as i64
+}
+
 fn build_locations_database(
     conn: &rusqlite::Connection,
+    catalog: i64,
     locations_str: &str,
 ) -> Result<(), crate::Error> {
-    const INIT_LOCATIONS_DB: &'static str = r#"
-      CREATE TABLE locations (                
-        id    TEXT NOT NULL,                 
-        name  TEXT NOT NULL,                 
-        state TEXT NOT NULL,                 
-        lat   REAL NOT NULL,                 
-        lon   REAL NOT NULL,                 
-        PRIMARY KEY (id) ON CONFLICT IGNORE) 
+    const QUERY_CATALOG_EXISTS: &'static str = r#"
+        SELECT EXISTS (SELECT 1 FROM nbmarch_sites WHERE catalog = ?)
     "#;
 
     const INSERT_LOCATION: &'static str = r#"
-        INSERT INTO locations (id, name, state, lat, lon) VALUES (?, ?, ?, ?, ?)
+        INSERT INTO nbmarch_sites (catalog, id, name, state, lat, lon) VALUES (?, ?, ?, ?, ?, ?)
     "#;
 
-    conn.execute(INIT_LOCATIONS_DB, rusqlite::NO_PARAMS)?;
+    let exists: bool = conn.query_row(QUERY_CATALOG_EXISTS, &[&catalog], |row| row.get(0))?;
+    if exists {
+        return Ok(());
+    }
 
     let mut stmt = conn.prepare(INSERT_LOCATION)?;
 
-    let mut rdr = csv::Reader::from_reader(locations_str.as_bytes());
-
-    for rec in rdr.records().filter_map(|res| res.ok()) {
-        if let (Some(id), Some(name), Some(state_prov), Some(lat_str), Some(lon_str)) =
-            (rec.get(0), rec.get(1), rec.get(2), rec.get(3), rec.get(4))
-        {
-            let latitude: f64 = if let Ok(val) = lat_str.parse() {
-                val
-            } else {
-                continue;
-            };
-
-            let longitude: f64 = if let Ok(val) = lon_str.parse() {
-                val
-            } else {
-                continue;
-            };
-
-            let id = id.trim();
-            let name = name.trim();
-            let state_prov = state_prov.trim();
-
-            stmt.execute(&[&id as &dyn ToSql, &name, &state_prov, &latitude, &longitude])?;
-        }
+    for (id, name, state_prov, latitude, longitude) in parse_locations(locations_str)? {
+        stmt.execute(&[
+            &catalog as &dyn ToSql,
+            &id,
+            &name,
+            &state_prov,
+            &latitude,
+            &longitude,
+        ])?;
     }
 
     Ok(())
 }
 
-fn find_exact_case_insensitive_match(
-    conn: &rusqlite::Connection,
-    site: &str,
-) -> Result<Option<SiteInfo>, crate::Error> {
-    let query_exact_case_insensitive_match = format!(
-        "SELECT id, name, state, lat, lon FROM locations WHERE id = '{}'",
-        site
-    );
-
-    Ok(conn
-        .query_row(
-            &query_exact_case_insensitive_match,
-            rusqlite::NO_PARAMS,
-            |row| {
-                let id = row.get(0)?;
-                let name = row.get(1)?;
-                let state_prov = row.get(2)?;
-                let latitude: f64 = row.get(3)?;
-                let longitude: f64 = row.get(4)?;
-
-                let latitude = latitude as f32;
-                let longitude = longitude as f32;
-
-                Ok(SiteInfo {
-                    id,
-                    name,
-                    state_prov,
-                    latitude,
-                    longitude,
-                })
-            },
-        )
-        .optional()?)
-}
+/// A row of a "locations.csv" file: id, name, state/province, latitude, and longitude.
+type LocationRow = (String, String, String, f64, f64);
 
-fn find_similar_sites(
-    conn: &rusqlite::Connection,
-    site: &str,
-) -> Result<Vec<SiteInfo>, crate::Error> {
-    let query_similar_sites = format!(
-        "SELECT id, name, state, lat, lon FROM locations WHERE id LIKE '%{}%' OR name LIKE '%{}%' OR state LIKE '{}'",
-        site, site, site
-    );
+/// Parse a "locations.csv" file. Rows that don't parse are skipped, but a file without any valid
+/// sites is an error so it never makes it into the local store.
+pub(crate) fn parse_locations(locations_str: &str) -> Result<Vec<LocationRow>, crate::Error> {
+    let mut rdr = csv::Reader::from_reader(locations_str.as_bytes());
 
-    let mut stmt = conn.prepare(&query_similar_sites)?;
+    if rdr.headers()?.len() < 5 {
+        return Err(crate::Error::general_error(
+            "locations.csv should have id, name, state, lat, and lon columns".to_owned(),
+        ));
+    }
 
-    let temp = stmt.query_map(rusqlite::NO_PARAMS, |row| {
-        let id = row.get(0)?;
-        let name = row.get(1)?;
-        let state_prov = row.get(2)?;
-        let latitude: f64 = row.get(3)?;
-        let longitude: f64 = row.get(4)?;
+    let rows: Vec<LocationRow> = rdr
+        .records()
+        .filter_map(|res| res.ok())
+        .filter_map(|rec| {
+            let id = rec.get(0)?.trim();
+            let name = rec.get(1)?.trim();
+            let state_prov = rec.get(2)?.trim();
+            let latitude: f64 = rec.get(3)?.trim().parse().ok()?;
+            let longitude: f64 = rec.get(4)?.trim().parse().ok()?;
 
-        let latitude = latitude as f32;
-        let longitude = longitude as f32;
+            if id.is_empty() || !latitude.is_finite() || !longitude.is_finite() {
+                return None;
+            }
 
-        Ok(SiteInfo {
-            id,
-            name,
-            state_prov,
-            latitude,
-            longitude,
+            Some((
+                id.to_owned(),
+                name.to_owned(),
+                state_prov.to_owned(),
+                latitude,
+                longitude,
+            ))
         })
-    })?;
+        .collect();
+
+    if rows.is_empty() {
+        return Err(crate::Error::general_error(
+            "locations.csv doesn't have any valid sites".to_owned(),
+        ));
+    }
+
+    Ok(rows)
+}
 
-    Ok(temp.filter_map(|res| res.ok()).collect())
+fn site_info_from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<SiteInfo> {
+    let id = row.get(0)?;
+    let name = row.get(1)?;
+    let state_prov = row.get(2)?;
+    let latitude: f64 = row.get(3)?;
+    let longitude: f64 = row.get(4)?;
+
+    let latitude = latitude as f32;
+    let longitude = longitude as f32;
+
+    Ok(SiteInfo {
+        id,
+        name,
+        state_prov,
+        latitude,
+        longitude,
+    })
 }
 
 /// All the available information about a site as retrived from the store.
@@ -190,6 +436,89 @@ pub struct SiteInfo {
     pub longitude: f32,
 }
 
+/// A candidate site for a request and how well it matches.
+#[derive(Debug, Clone)]
+pub struct SiteMatch {
+    /// The site.
+    pub site: SiteInfo,
+    /// How well the site matches the request, from 0.0 to 1.0 where 1.0 is an exact match of the
+    /// id or name.
+    pub score: f64,
+}
+
+impl Display for SiteMatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        write!(f, "{:4.2} {}", self.score, self.site)
+    }
+}
+
+/// A latitude/longitude box for site searches.
+///
+/// If `west` is greater than `east` the box wraps across the 180th meridian.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoundingBox {
+    /// The southern edge in degrees.
+    pub south: f64,
+    /// The northern edge in degrees.
+    pub north: f64,
+    /// The western edge in degrees.
+    pub west: f64,
+    /// The eastern edge in degrees.
+    pub east: f64,
+}
+
+impl BoundingBox {
+    /// Check if a point is inside the box, edges included.
+    pub fn contains(&self, latitude: f64, longitude: f64) -> bool {
+        let in_lat = latitude >= self.south && latitude <= self.north;
+
+        let in_lon = if self.west <= self.east {
+            longitude >= self.west && longitude <= self.east
+        } else {
+            longitude >= self.west || longitude <= self.east
+        };
+
+        in_lat && in_lon
+    }
+}
+
+impl SiteInfo {
+    /// The ids of a list of sites, e.g. the results of
+    /// [NBMStore::sites_within()](crate::NBMStore::sites_within), ready to pass to bulk operations
+    /// like [NBMStore::prefetch()](crate::NBMStore::prefetch) and
+    /// [NBMStore::find_gaps()](crate::NBMStore::find_gaps).
+    pub fn ids(sites: &[SiteInfo]) -> Vec<&str> {
+        sites.iter().map(|site| site.id.as_str()).collect()
+    }
+
+    /// The great circle distance in kilometers from this site to a point.
+    pub fn distance_to(&self, latitude: f64, longitude: f64) -> f64 {
+        great_circle_distance(
+            f64::from(self.latitude),
+            f64::from(self.longitude),
+            latitude,
+            longitude,
+        )
+    }
+}
+
+/// The great circle distance in kilometers between two points using the haversine formula.
+fn great_circle_distance(lat1: f64, lon1: f64, lat2: f64, lon2: f64) -> f64 {
+    const EARTH_RADIUS_KM: f64 = 6371.0;
+
+    let (lat1, lon1, lat2, lon2) = (
+        lat1.to_radians(),
+        lon1.to_radians(),
+        lat2.to_radians(),
+        lon2.to_radians(),
+    );
+
+    let a = ((lat2 - lat1) / 2.0).sin().powi(2)
+        + lat1.cos() * lat2.cos() * ((lon2 - lon1) / 2.0).sin().powi(2);
+
+    2.0 * EARTH_RADIUS_KM * a.sqrt().min(1.0).asin()
+}
+
 impl Display for SiteInfo {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
         write!(
@@ -199,3 +528,210 @@ impl Display for SiteInfo {
         )
     }
 }
+
+#[cfg(test)]
+pub(crate) mod test {
+    const LOCATIONS: &str = "id,name,state,lat,lon\n\
+        KONL,O'NEILL,NE,42.47,-98.69\n\
+        KMSO,MISSOULA,MT,46.92,-114.09\n\
+        KLGU,LOGAN,UT,41.79,-111.85\n\
+        K6L4,LOGAN,WV,37.86,-81.99\n\
+        LGNM8,LOGAN PASS,MT,48.70,-113.72\n";
+
+    pub(crate) struct TestMetadata {
+        _temp_db_file: tempfile::NamedTempFile,
+        pub(crate) metadata: crate::metadata::Metadata,
+        pub(crate) init_time: chrono::NaiveDateTime,
+    }
+
+    /// Metadata in a temporary file with [LOCATIONS] added for 2021-02-28 13Z.
+    pub(crate) fn test_metadata() -> Result<TestMetadata, Box<dyn std::error::Error>> {
+        let temp_db_file = tempfile::NamedTempFile::new()?;
+        let metadata = crate::metadata::Metadata::connect(temp_db_file.path())?;
+
+        let init_time = chrono::NaiveDate::from_ymd(2021, 2, 28).and_hms(13, 0, 0);
+        metadata.add_locations(init_time, LOCATIONS)?;
+
+        Ok(TestMetadata {
+            _temp_db_file: temp_db_file,
+            metadata,
+            init_time,
+        })
+    }
+
+    #[test]
+    fn test_parse_locations() {
+        let rows = super::parse_locations(LOCATIONS).unwrap();
+        assert_eq!(rows.len(), 5);
+        assert_eq!(
+            rows[1],
+            (
+                "KMSO".to_owned(),
+                "MISSOULA".to_owned(),
+                "MT".to_owned(),
+                46.92,
+                -114.09
+            )
+        );
+
+        assert!(super::parse_locations("id,name,state,lat,lon\n").is_err());
+        assert!(super::parse_locations("id,name\nKMSO,MISSOULA\n").is_err());
+        assert!(super::parse_locations("a,b,c,d,e\nx,y,z,north,west\n").is_err());
+    }
+
+    #[test]
+    fn test_catalog_id_is_stable() {
+        // Published FNV-1a test vectors, the ids are saved so they must never change.
+        assert_eq!(super::catalog_id("") as u64, 0xcbf2_9ce4_8422_2325);
+        assert_eq!(super::catalog_id("a") as u64, 0xaf63_dc4c_8601_ec8c);
+        assert_eq!(super::catalog_id("foobar") as u64, 0x8594_4171_f739_67e8);
+
+        assert_eq!(super::catalog_id(LOCATIONS), super::catalog_id(LOCATIONS));
+        assert_ne!(
+            super::catalog_id(LOCATIONS),
+            super::catalog_id(&LOCATIONS[1..])
+        );
+    }
+
+    #[test]
+    fn test_add_invalid_locations() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_db_file = tempfile::NamedTempFile::new()?;
+        let metadata = crate::metadata::Metadata::connect(temp_db_file.path())?;
+
+        let init_time = chrono::NaiveDate::from_ymd(2021, 2, 28).and_hms(13, 0, 0);
+        assert!(metadata.add_locations(init_time, "error,page\n").is_err());
+        assert!(metadata.locations(init_time)?.is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_validate_against_catalog() -> Result<(), Box<dyn std::error::Error>> {
+        let test = test_metadata()?;
+
+        let locations = test.metadata.locations(test.init_time)?.unwrap();
+        assert_eq!(&locations.validate("kmso")?.id, "KMSO");
+        assert_eq!(&locations.validate("O'Neill")?.id, "KONL");
+        assert_eq!(&locations.validate("missoula")?.id, "KMSO");
+
+        assert_eq!(&locations.validate("misoula")?.id, "KMSO");
+        assert_eq!(&locations.validate("MSO")?.id, "KMSO");
+        assert_eq!(&locations.validate("id:kmso")?.id, "KMSO");
+        assert_eq!(&locations.validate("Missoula MT")?.id, "KMSO");
+        assert_eq!(&locations.validate("Logan, UT")?.id, "KLGU");
+        assert_eq!(&locations.validate("logan wv")?.id, "K6L4");
+        assert_eq!(&locations.validate("name:logan state:mt")?.id, "LGNM8");
+        assert_eq!(&locations.validate("state:NE")?.id, "KONL");
+        assert_eq!(&locations.validate("logan pass")?.id, "LGNM8");
+
+        match locations.validate("logan") {
+            Err(crate::Error::AmbiguousSite { matches }) => {
+                let ids: Vec<&str> = matches.iter().map(|m| m.site.id.as_str()).collect();
+                assert_eq!(ids, vec!["K6L4", "KLGU", "LGNM8"]);
+                assert!(matches[1].score > matches[2].score);
+            }
+            res => panic!("Expected an ambiguous site, got {:?}", res),
+        }
+
+        match locations.validate("id:MSO8") {
+            Err(crate::Error::NoMatch(_)) => {}
+            res => panic!("Expected no match, got {:?}", res),
+        }
+
+        match locations.validate("%") {
+            Err(crate::Error::NoMatch(_)) => {}
+            res => panic!("Expected no match, got {:?}", res),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_list_sites() -> Result<(), Box<dyn std::error::Error>> {
+        let test = test_metadata()?;
+
+        let locations = test.metadata.locations(test.init_time)?.unwrap();
+
+        let ids = |sites: Vec<super::SiteInfo>| -> Vec<String> {
+            sites.into_iter().map(|site| site.id).collect()
+        };
+
+        assert_eq!(locations.list(None, None)?.len(), 5);
+        assert_eq!(
+            ids(locations.list(Some("mt"), None)?),
+            vec!["KMSO", "LGNM8"]
+        );
+        assert_eq!(ids(locations.list(None, Some("kl"))?), vec!["KLGU"]);
+        assert_eq!(ids(locations.list(Some("MT"), Some("K"))?), vec!["KMSO"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_spatial_searches() -> Result<(), Box<dyn std::error::Error>> {
+        let test = test_metadata()?;
+
+        let locations = test.metadata.locations(test.init_time)?.unwrap();
+
+        let ids = |sites: Vec<super::SiteInfo>| -> Vec<String> {
+            sites.into_iter().map(|site| site.id).collect()
+        };
+
+        let near_missoula = locations.within(46.87, -113.99, 250.0, None)?;
+        assert_eq!(ids(near_missoula), vec!["KMSO", "LGNM8"]);
+
+        let in_utah = locations.within(46.87, -113.99, 1000.0, Some("ut"))?;
+        assert_eq!(ids(in_utah), vec!["KLGU"]);
+
+        let bbox = super::BoundingBox {
+            south: 40.0,
+            north: 49.0,
+            west: -115.0,
+            east: -110.0,
+        };
+        assert_eq!(
+            ids(locations.in_box(&bbox, None)?),
+            vec!["KLGU", "KMSO", "LGNM8"]
+        );
+        assert_eq!(
+            ids(locations.in_box(&bbox, Some("MT"))?),
+            vec!["KMSO", "LGNM8"]
+        );
+
+        let across_dateline = super::BoundingBox {
+            south: -10.0,
+            north: 10.0,
+            west: 170.0,
+            east: -170.0,
+        };
+        assert!(across_dateline.contains(0.0, 179.0));
+        assert!(across_dateline.contains(0.0, -179.0));
+        assert!(!across_dateline.contains(0.0, 0.0));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_great_circle_distance() {
+        let one_degree = super::great_circle_distance(0.0, 0.0, 0.0, 1.0);
+        assert!((one_degree - 111.19).abs() < 0.01);
+
+        let across_dateline = super::great_circle_distance(0.0, 179.5, 0.0, -179.5);
+        assert!((across_dateline - one_degree).abs() < 1.0e-6);
+    }
+
+    #[test]
+    fn test_nearest_sites() -> Result<(), Box<dyn std::error::Error>> {
+        let test = test_metadata()?;
+
+        let locations = test.metadata.locations(test.init_time)?.unwrap();
+
+        let nearest = locations.nearest(46.87, -113.99, 2)?;
+        assert_eq!(nearest.len(), 2);
+        assert_eq!(&nearest[0].0.id, "KMSO");
+        assert_eq!(&nearest[1].0.id, "LGNM8");
+        assert!(nearest[0].1 < nearest[1].1);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,118 @@
+/// A version of the NBM and where its files are kept in the remote archive.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NBMVersion {
+    /// The model version, e.g. "4.0".
+    pub name: String,
+    /// The directory in the remote archive that holds files from this version, e.g. "NBM4.0".
+    pub path: String,
+}
+
+impl NBMVersion {
+    /// Create a new version description.
+    pub fn new(name: &str, path: &str) -> Self {
+        Self {
+            name: name.to_owned(),
+            path: path.to_owned(),
+        }
+    }
+}
+
+impl std::fmt::Display for NBMVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        write!(f, "NBM {}", self.name)
+    }
+}
+
+/// Maps initialization times to the [NBMVersion] that produced them.
+///
+/// Each version is in effect from its start time until the start time of the next version, and
+/// the oldest version is also used for any time before the table starts. The default table knows
+/// about the archive before NBM 4.0 ("NBM/") and NBM 4.0 ("NBM4.0/"), which started with the
+/// first cycle after 2020-09-23 00Z. Newer versions can be added as they show up in the archive.
+#[derive(Debug, Clone)]
+pub struct VersionTable {
+    // Sorted by start time, oldest first.
+    versions: Vec<(chrono::NaiveDateTime, NBMVersion)>,
+}
+
+impl VersionTable {
+    /// Add a version that started with the initialization time `starts`.
+    ///
+    /// If a version already starts at that time, it is replaced.
+    pub fn with_version(mut self, starts: chrono::NaiveDateTime, version: NBMVersion) -> Self {
+        self.register(starts, version);
+        self
+    }
+
+    /// Add a version that started with the initialization time `starts`, see
+    /// [Self::with_version()].
+    pub fn register(&mut self, starts: chrono::NaiveDateTime, version: NBMVersion) {
+        match self.versions.binary_search_by_key(&starts, |(st, _)| *st) {
+            Ok(idx) => self.versions[idx].1 = version,
+            Err(idx) => self.versions.insert(idx, (starts, version)),
+        }
+    }
+
+    /// Find the version that produced the files for an initialization time.
+    pub fn version_at(&self, init_time: chrono::NaiveDateTime) -> &NBMVersion {
+        self.versions
+            .iter()
+            .rev()
+            .find(|(starts, _)| *starts <= init_time)
+            .or_else(|| self.versions.first())
+            .map(|(_, version)| version)
+            .expect("version table is never empty")
+    }
+}
+
+impl Default for VersionTable {
+    fn default() -> Self {
+        Self {
+            versions: vec![
+                (
+                    chrono::NaiveDate::from_ymd(2000, 1, 1).and_hms(0, 0, 0),
+                    NBMVersion::new("3.x", "NBM"),
+                ),
+                (
+                    chrono::NaiveDate::from_ymd(2020, 9, 23).and_hms(1, 0, 0),
+                    NBMVersion::new("4.0", "NBM4.0"),
+                ),
+            ],
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_default_versions() {
+        let versions = VersionTable::default();
+
+        let init_time = chrono::NaiveDate::from_ymd(2020, 9, 22).and_hms(19, 0, 0);
+        assert_eq!(versions.version_at(init_time).path, "NBM");
+
+        let init_time = chrono::NaiveDate::from_ymd(2020, 9, 23).and_hms(1, 0, 0);
+        assert_eq!(versions.version_at(init_time).path, "NBM4.0");
+
+        let init_time = chrono::NaiveDate::from_ymd(1999, 1, 1).and_hms(1, 0, 0);
+        assert_eq!(versions.version_at(init_time).path, "NBM");
+    }
+
+    #[test]
+    fn test_register_version() {
+        let v41_starts = chrono::NaiveDate::from_ymd(2022, 1, 18).and_hms(13, 0, 0);
+        let versions =
+            VersionTable::default().with_version(v41_starts, NBMVersion::new("4.1", "NBM4.1"));
+
+        let init_time = chrono::NaiveDate::from_ymd(2022, 1, 18).and_hms(7, 0, 0);
+        assert_eq!(versions.version_at(init_time).name, "4.0");
+
+        let init_time = chrono::NaiveDate::from_ymd(2022, 1, 18).and_hms(13, 0, 0);
+        assert_eq!(versions.version_at(init_time).name, "4.1");
+
+        let versions = versions.with_version(v41_starts, NBMVersion::new("4.1", "NBM41"));
+        assert_eq!(versions.version_at(init_time).path, "NBM41");
+    }
+}
@@ -3,10 +3,23 @@ struct TestArchive {
     arch: nbmarch::NBMStore,
 }
 
+/// Serve the files in tests/fixtures instead of downloading them. Set `NBMARCH_RECORD` to
+/// download any that are missing and save them there.
+fn fixtures() -> nbmarch::ReplayFetcher {
+    let fetcher =
+        nbmarch::ReplayFetcher::new(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures"));
+
+    if std::env::var_os("NBMARCH_RECORD").is_some() {
+        fetcher.with_recorder(nbmarch::ReqwestFetcher::new())
+    } else {
+        fetcher
+    }
+}
+
 fn create_test_archive() -> Result<TestArchive, Box<dyn std::error::Error>> {
     let temp_db_file = tempfile::NamedTempFile::new()?;
     let db_fname = temp_db_file.path();
-    let arch = nbmarch::NBMStore::connect(db_fname)?;
+    let arch = nbmarch::NBMStore::connect(db_fname)?.with_fetcher(fixtures());
 
     Ok(TestArchive {
         _temp_db_file: temp_db_file,
@@ -0,0 +1,37 @@
+//! Tests against files recorded from the NOAA archive, see tests/recorded/README.md.
+
+/// Serve the files in tests/recorded. Set `NBMARCH_RECORD` to download any that are missing and
+/// save them there.
+fn recorded() -> nbmarch::ReplayFetcher {
+    let fetcher =
+        nbmarch::ReplayFetcher::new(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/recorded"));
+
+    if std::env::var_os("NBMARCH_RECORD").is_some() {
+        fetcher.with_recorder(nbmarch::ReqwestFetcher::new())
+    } else {
+        fetcher
+    }
+}
+
+#[test]
+#[ignore = "needs files recorded from the NOAA archive, see tests/recorded/README.md"]
+fn test_recorded_files_parse() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_db_file = tempfile::NamedTempFile::new()?;
+    let arch = nbmarch::NBMStore::connect(temp_db_file.path())?
+        .with_fetcher(recorded())
+        .with_retry_policy(nbmarch::RetryPolicy::no_retries());
+
+    let init_time = chrono::NaiveDate::from_ymd(2021, 2, 28).and_hms(13, 0, 0);
+
+    let validation = arch.validate_request("missoula", init_time)?;
+    assert_eq!(&validation.site.id, "KMSO");
+    assert_eq!(validation.initialization_time, init_time);
+
+    let csv = arch.retrieve_csv(validation.clone())?;
+    assert!(csv.starts_with("validTime,"));
+    assert!(csv.lines().count() > 100);
+
+    arch.retrieve(validation)?;
+
+    Ok(())
+}